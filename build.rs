@@ -7,6 +7,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     tonic_build::configure()
         .build_server(true)
         .file_descriptor_set_path(out_dir.join("proto_descriptor.bin"))
+        .boxed(".finance_control.AccountEvent.event.transaction_posted")
+        .boxed(".finance_control.AccountEvent.event.transaction_voided")
         .compile(&["proto/finance_control.proto"], &["proto"])
         .unwrap();
 
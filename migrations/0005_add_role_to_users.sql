@@ -0,0 +1,8 @@
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'userrole') THEN
+        CREATE TYPE UserRole AS ENUM ('USER', 'ADMIN', 'AUDITOR');
+    END IF;
+END $$;
+
+ALTER TABLE users ADD COLUMN role UserRole NOT NULL DEFAULT 'USER';
@@ -0,0 +1,113 @@
+-- Repairs the balances written by the old float path. Its CreateBankAccount
+-- stored the initial deposit in cents without recording a transaction, and
+-- its ExecuteTransaction read the balance back as units, applied the amount
+-- and wrote `(balance * 100.0) as i64`, which truncates and loses up to a
+-- cent per write. Amounts were stored in cents, rounded, so only the
+-- balances need repairing.
+--
+-- Every account is replayed with the old float8 arithmetic to find the
+-- deposit that leads to its stored balance. That deposit is recorded as the
+-- opening transaction CreateBankAccount now writes, and the balance is set to
+-- the deposit plus the history, which restores the truncated cents. Accounts
+-- that no deposit replays to, e.g. because of concurrent writes, make the
+-- migration fail with a report instead of being guessed at.
+CREATE TABLE balance_repairs (
+  account_id UUID NOT NULL,
+  stored_balance BIGINT NOT NULL,
+  repaired_balance BIGINT NOT NULL,
+  opening_deposit BIGINT NOT NULL,
+  cause VARCHAR(32) NOT NULL,
+  repaired_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (account_id) REFERENCES bank_accounts(id),
+
+  CONSTRAINT "balance_repairs_pkey" PRIMARY KEY ("account_id")
+);
+
+-- Balance the old ExecuteTransaction path ends up with when the account was
+-- created with `deposit` cents.
+CREATE FUNCTION replay_float_balance(account UUID, deposit BIGINT) RETURNS BIGINT AS $$
+DECLARE
+    balance BIGINT := deposit;
+    entry RECORD;
+BEGIN
+    FOR entry IN
+        SELECT amount, transaction_type FROM transactions
+        WHERE origin_account_id = account
+        ORDER BY created_at, id
+    LOOP
+        balance := trunc(
+            (balance::float8 / 100::float8
+                + (CASE entry.transaction_type WHEN 'INCOME' THEN 1 ELSE -1 END)
+                    * (entry.amount::float8 / 100::float8))
+            * 100::float8
+        )::bigint;
+    END LOOP;
+
+    RETURN balance;
+END;
+$$ LANGUAGE plpgsql;
+
+DO $$
+DECLARE
+    account RECORD;
+    candidate BIGINT;
+    deposit BIGINT;
+    cause VARCHAR(32);
+    unresolved TEXT[] := '{}';
+BEGIN
+    FOR account IN
+        SELECT
+            a.id,
+            a.balance,
+            a.created_at,
+            COALESCE(SUM(CASE t.transaction_type WHEN 'INCOME' THEN t.amount ELSE -t.amount END), 0)::bigint AS history,
+            COUNT(t.id)::bigint AS writes
+        FROM bank_accounts a
+        LEFT JOIN transactions t ON t.origin_account_id = a.id
+        GROUP BY a.id, a.balance, a.created_at
+    LOOP
+        deposit := NULL;
+
+        -- Each write loses at most a cent, so the deposit is at most `writes`
+        -- cents above the gap. The smallest one that replays to the stored
+        -- balance is the one with the fewest lost cents.
+        FOR candidate IN
+            SELECT generate_series(GREATEST(account.balance - account.history, 0), account.balance - account.history + account.writes)
+        LOOP
+            IF replay_float_balance(account.id, candidate) = account.balance THEN
+                deposit := candidate;
+                EXIT;
+            END IF;
+        END LOOP;
+
+        IF deposit IS NULL THEN
+            unresolved := unresolved || format(
+                '%s: stored balance %s, transactions sum to %s over %s writes',
+                account.id, account.balance, account.history, account.writes
+            );
+            CONTINUE;
+        END IF;
+
+        CONTINUE WHEN deposit = 0 AND account.history = account.balance;
+
+        cause := CASE WHEN deposit + account.history = account.balance THEN 'opening_deposit' ELSE 'truncated' END;
+
+        IF deposit > 0 THEN
+            INSERT INTO transactions (id, amount, transaction_type, origin_account_id, description, created_at)
+            VALUES (gen_random_uuid(), deposit, 'INCOME', account.id, 'Opening balance', account.created_at);
+        END IF;
+
+        UPDATE bank_accounts SET balance = deposit + account.history WHERE id = account.id;
+
+        INSERT INTO balance_repairs (account_id, stored_balance, repaired_balance, opening_deposit, cause)
+        VALUES (account.id, account.balance, deposit + account.history, deposit, cause);
+    END LOOP;
+
+    IF cardinality(unresolved) > 0 THEN
+        RAISE EXCEPTION '% account balances written by the old float path can''t be reconstructed from their transactions', cardinality(unresolved)
+            USING DETAIL = array_to_string(unresolved, E'\n'),
+                  HINT = 'Correct these balances by hand; the migration runs again on the next start.';
+    END IF;
+END $$;
+
+DROP FUNCTION replay_float_balance(UUID, BIGINT);
@@ -0,0 +1,5 @@
+ALTER TABLE transactions
+  ADD COLUMN destination_account_id UUID DEFAULT NULL REFERENCES bank_accounts(id),
+  ADD COLUMN transfer_id UUID DEFAULT NULL;
+
+CREATE INDEX "transactions_transfer_id_idx" ON "transactions"("transfer_id");
@@ -0,0 +1,78 @@
+CREATE TABLE journal_entries (
+  id UUID,
+  description VARCHAR(255) DEFAULT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+
+  CONSTRAINT "journal_entries_pkey" PRIMARY KEY ("id")
+);
+
+-- Positive amounts are debits and negative amounts are credits. A NULL
+-- bank_account_id is the outside world, the counterpart of every INCOME
+-- and OUTCOME.
+CREATE TABLE ledger_entries (
+  id UUID,
+  journal_entry_id UUID NOT NULL,
+  bank_account_id UUID DEFAULT NULL,
+  amount BIGINT NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (journal_entry_id) REFERENCES journal_entries(id),
+  FOREIGN KEY (bank_account_id) REFERENCES bank_accounts(id),
+
+  CONSTRAINT "ledger_entries_pkey" PRIMARY KEY ("id")
+);
+
+CREATE INDEX "ledger_entries_journal_entry_id_idx" ON "ledger_entries"("journal_entry_id");
+CREATE INDEX "ledger_entries_bank_account_id_idx" ON "ledger_entries"("bank_account_id");
+
+ALTER TABLE transactions ADD COLUMN journal_entry_id UUID DEFAULT NULL REFERENCES journal_entries(id);
+
+-- Backfill: a transfer shares one journal entry keyed by its transfer id,
+-- every other transaction gets its own entry keyed by the transaction id.
+INSERT INTO journal_entries (id, description, created_at)
+SELECT COALESCE(transfer_id, id), MIN(description), MIN(created_at)
+FROM transactions
+GROUP BY COALESCE(transfer_id, id);
+
+UPDATE transactions SET journal_entry_id = COALESCE(transfer_id, id);
+
+ALTER TABLE transactions ALTER COLUMN journal_entry_id SET NOT NULL;
+
+INSERT INTO ledger_entries (id, journal_entry_id, bank_account_id, amount, created_at)
+SELECT
+    gen_random_uuid(),
+    journal_entry_id,
+    origin_account_id,
+    CASE transaction_type WHEN 'INCOME' THEN amount ELSE -amount END,
+    created_at
+FROM transactions;
+
+INSERT INTO ledger_entries (id, journal_entry_id, bank_account_id, amount, created_at)
+SELECT
+    gen_random_uuid(),
+    journal_entry_id,
+    NULL,
+    CASE transaction_type WHEN 'INCOME' THEN -amount ELSE amount END,
+    created_at
+FROM transactions
+WHERE transfer_id IS NULL;
+
+CREATE FUNCTION check_journal_entry_balanced() RETURNS TRIGGER AS $$
+BEGIN
+    IF (SELECT SUM(amount) FROM ledger_entries WHERE journal_entry_id = NEW.journal_entry_id) <> 0 THEN
+        RAISE EXCEPTION 'Journal entry % is not balanced', NEW.journal_entry_id;
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE CONSTRAINT TRIGGER "ledger_entries_balanced"
+    AFTER INSERT OR UPDATE ON ledger_entries
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE FUNCTION check_journal_entry_balanced();
+
+CREATE VIEW ledger_account_balances AS
+SELECT bank_account_id, SUM(amount) AS balance
+FROM ledger_entries
+WHERE bank_account_id IS NOT NULL
+GROUP BY bank_account_id;
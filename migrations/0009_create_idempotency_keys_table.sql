@@ -0,0 +1,11 @@
+CREATE TABLE idempotency_keys (
+  user_id UUID NOT NULL,
+  method VARCHAR(255) NOT NULL,
+  key VARCHAR(255) NOT NULL,
+  request_hash BYTEA NOT NULL,
+  response BYTEA DEFAULT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (user_id) REFERENCES users(id),
+
+  CONSTRAINT "idempotency_keys_pkey" PRIMARY KEY ("user_id", "method", "key")
+);
@@ -0,0 +1,2 @@
+CREATE INDEX "transactions_origin_account_id_created_at_idx"
+    ON "transactions"("origin_account_id", "created_at", "id");
@@ -0,0 +1,3 @@
+-- Bumped whenever outstanding access tokens of the user must stop working,
+-- e.g. after a password change.
+ALTER TABLE users ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
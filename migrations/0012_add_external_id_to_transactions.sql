@@ -0,0 +1,5 @@
+-- Id of the bank statement line a transaction was imported from (OFX FITID
+-- or the CSV id column), so the same line is never imported twice.
+ALTER TABLE transactions ADD COLUMN external_id VARCHAR(255) DEFAULT NULL;
+
+CREATE UNIQUE INDEX "transactions_origin_account_id_external_id_key" ON "transactions"("origin_account_id", "external_id");
@@ -0,0 +1,49 @@
+-- Categories without a user are the system defaults, shared by everyone.
+CREATE TABLE categories (
+  id UUID,
+  user_id UUID DEFAULT NULL,
+  parent_id UUID DEFAULT NULL,
+  name VARCHAR(255) NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (user_id) REFERENCES users(id),
+  FOREIGN KEY (parent_id) REFERENCES categories(id),
+
+  CONSTRAINT "categories_pkey" PRIMARY KEY ("id")
+);
+
+CREATE UNIQUE INDEX "categories_user_id_parent_id_name_key" ON "categories"("user_id", "parent_id", lower("name")) NULLS NOT DISTINCT;
+
+INSERT INTO categories (id, name)
+SELECT gen_random_uuid(), name
+FROM unnest(ARRAY[
+  'Groceries', 'Salary', 'Rent', 'Utilities', 'Transport',
+  'Restaurants', 'Health', 'Entertainment', 'Shopping', 'Savings'
+]) AS name;
+
+ALTER TABLE transactions
+  ADD COLUMN category_id UUID DEFAULT NULL REFERENCES categories(id) ON DELETE SET NULL;
+
+CREATE INDEX "transactions_category_id_idx" ON "transactions"("category_id");
+
+CREATE TABLE tags (
+  id UUID,
+  user_id UUID NOT NULL,
+  name VARCHAR(64) NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (user_id) REFERENCES users(id),
+
+  CONSTRAINT "tags_pkey" PRIMARY KEY ("id")
+);
+
+CREATE UNIQUE INDEX "tags_user_id_name_key" ON "tags"("user_id", "name");
+
+CREATE TABLE transaction_tags (
+  transaction_id UUID NOT NULL,
+  tag_id UUID NOT NULL,
+  FOREIGN KEY (transaction_id) REFERENCES transactions(id),
+  FOREIGN KEY (tag_id) REFERENCES tags(id),
+
+  CONSTRAINT "transaction_tags_pkey" PRIMARY KEY ("transaction_id", "tag_id")
+);
+
+CREATE INDEX "transaction_tags_tag_id_idx" ON "transaction_tags"("tag_id");
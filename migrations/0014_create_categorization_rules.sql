@@ -0,0 +1,22 @@
+-- Unset conditions match every transaction. Rules run by ascending priority.
+CREATE TABLE categorization_rules (
+  id UUID,
+  user_id UUID NOT NULL,
+  priority INTEGER NOT NULL,
+  description_contains VARCHAR(255) DEFAULT NULL,
+  description_pattern VARCHAR(1024) DEFAULT NULL,
+  min_amount BIGINT DEFAULT NULL,
+  max_amount BIGINT DEFAULT NULL,
+  account_id UUID DEFAULT NULL,
+  transaction_type TransactionType DEFAULT NULL,
+  category_id UUID DEFAULT NULL,
+  tags TEXT[] NOT NULL DEFAULT '{}',
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (user_id) REFERENCES users(id),
+  FOREIGN KEY (account_id) REFERENCES bank_accounts(id) ON DELETE CASCADE,
+  FOREIGN KEY (category_id) REFERENCES categories(id) ON DELETE CASCADE,
+
+  CONSTRAINT "categorization_rules_pkey" PRIMARY KEY ("id")
+);
+
+CREATE INDEX "categorization_rules_user_id_priority_idx" ON "categorization_rules"("user_id", "priority");
@@ -0,0 +1,15 @@
+-- A monthly spending limit of a user on a category and its subcategories.
+CREATE TABLE budgets (
+  id UUID,
+  user_id UUID NOT NULL,
+  category_id UUID NOT NULL,
+  limit_amount BIGINT NOT NULL CHECK (limit_amount > 0),
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (user_id) REFERENCES users(id),
+  FOREIGN KEY (category_id) REFERENCES categories(id) ON DELETE CASCADE,
+
+  CONSTRAINT "budgets_pkey" PRIMARY KEY ("id")
+);
+
+CREATE UNIQUE INDEX "budgets_user_id_category_id_key" ON "budgets"("user_id", "category_id");
@@ -0,0 +1,51 @@
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'schedulefrequency') THEN
+        CREATE TYPE ScheduleFrequency AS ENUM ('ONCE', 'DAILY', 'WEEKLY', 'MONTHLY');
+    END IF;
+END $$;
+
+-- `next_occurrence_at` is NULL once the schedule ended or was cancelled.
+CREATE TABLE scheduled_transactions (
+  id UUID,
+  user_id UUID NOT NULL,
+  account_id UUID NOT NULL,
+  amount BIGINT NOT NULL,
+  transaction_type TransactionType NOT NULL,
+  description VARCHAR(255) DEFAULT NULL,
+  category_id UUID DEFAULT NULL,
+  tags TEXT[] NOT NULL DEFAULT '{}',
+  frequency ScheduleFrequency NOT NULL,
+  interval INTEGER NOT NULL DEFAULT 1,
+  day_of_month INTEGER DEFAULT NULL,
+  starts_at TIMESTAMP NOT NULL,
+  ends_at TIMESTAMP DEFAULT NULL,
+  max_occurrences INTEGER DEFAULT NULL,
+  occurrences INTEGER NOT NULL DEFAULT 0,
+  next_occurrence_at TIMESTAMP DEFAULT NULL,
+  last_error VARCHAR(255) DEFAULT NULL,
+  cancelled_at TIMESTAMP DEFAULT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (user_id) REFERENCES users(id),
+  FOREIGN KEY (account_id) REFERENCES bank_accounts(id),
+  FOREIGN KEY (category_id) REFERENCES categories(id) ON DELETE SET NULL,
+
+  CONSTRAINT "scheduled_transactions_pkey" PRIMARY KEY ("id")
+);
+
+CREATE INDEX "scheduled_transactions_next_occurrence_at_idx" ON "scheduled_transactions"("next_occurrence_at") WHERE next_occurrence_at IS NOT NULL;
+CREATE INDEX "scheduled_transactions_user_id_idx" ON "scheduled_transactions"("user_id");
+
+-- One row per handled occurrence, written in the same DB transaction as the
+-- posted transaction, so an occurrence is never posted twice.
+CREATE TABLE scheduled_occurrences (
+  scheduled_transaction_id UUID NOT NULL,
+  occurrence_at TIMESTAMP NOT NULL,
+  transaction_id UUID DEFAULT NULL,
+  error VARCHAR(255) DEFAULT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (scheduled_transaction_id) REFERENCES scheduled_transactions(id),
+  FOREIGN KEY (transaction_id) REFERENCES transactions(id),
+
+  CONSTRAINT "scheduled_occurrences_pkey" PRIMARY KEY ("scheduled_transaction_id", "occurrence_at")
+);
@@ -0,0 +1,57 @@
+-- A reversal is a compensating transaction pointing at the one it cancels.
+-- The unique index makes sure a transaction is reversed at most once.
+ALTER TABLE transactions
+  ADD COLUMN reversal_of UUID DEFAULT NULL REFERENCES transactions(id),
+  ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+
+CREATE UNIQUE INDEX "transactions_reversal_of_key" ON "transactions"("reversal_of");
+
+-- Every amendment keeps the values it replaced. The category isn't a
+-- foreign key so the history survives the deletion of the category.
+CREATE TABLE transaction_versions (
+  transaction_id UUID NOT NULL,
+  version INTEGER NOT NULL,
+  description VARCHAR(255) DEFAULT NULL,
+  category_id UUID DEFAULT NULL,
+  amendment_reason VARCHAR(255) DEFAULT NULL,
+  superseded_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  FOREIGN KEY (transaction_id) REFERENCES transactions(id),
+
+  CONSTRAINT "transaction_versions_pkey" PRIMARY KEY ("transaction_id", "version")
+);
+
+-- Only the description, the category and the version of a posted
+-- transaction can change; the history can't change at all.
+CREATE FUNCTION reject_transaction_rewrite() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE'
+        OR NEW.id <> OLD.id
+        OR NEW.amount <> OLD.amount
+        OR NEW.transaction_type <> OLD.transaction_type
+        OR NEW.origin_account_id <> OLD.origin_account_id
+        OR NEW.destination_account_id IS DISTINCT FROM OLD.destination_account_id
+        OR NEW.transfer_id IS DISTINCT FROM OLD.transfer_id
+        OR NEW.journal_entry_id <> OLD.journal_entry_id
+        OR NEW.external_id IS DISTINCT FROM OLD.external_id
+        OR NEW.reversal_of IS DISTINCT FROM OLD.reversal_of
+        OR NEW.created_at IS DISTINCT FROM OLD.created_at THEN
+        RAISE EXCEPTION 'Transaction % can''t be rewritten', OLD.id;
+    END IF;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "transactions_immutable"
+    BEFORE UPDATE OR DELETE ON transactions
+    FOR EACH ROW EXECUTE FUNCTION reject_transaction_rewrite();
+
+CREATE FUNCTION reject_transaction_version_rewrite() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'Version % of transaction % can''t be rewritten', OLD.version, OLD.transaction_id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "transaction_versions_immutable"
+    BEFORE UPDATE OR DELETE ON transaction_versions
+    FOR EACH ROW EXECUTE FUNCTION reject_transaction_version_rewrite();
@@ -0,0 +1,41 @@
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'transactionstatus') THEN
+        CREATE TYPE TransactionStatus AS ENUM ('PENDING', 'POSTED', 'VOIDED');
+    END IF;
+END $$;
+
+-- A PENDING transaction has its journal entry but no ledger postings until
+-- it is POSTED. A pending OUTCOME holds its amount out of the available
+-- balance until it is posted or VOIDED.
+ALTER TABLE transactions ADD COLUMN status TransactionStatus NOT NULL DEFAULT 'POSTED';
+
+CREATE INDEX "transactions_pending_idx" ON "transactions"("origin_account_id") WHERE status = 'PENDING';
+
+ALTER TABLE bank_accounts ADD COLUMN available_balance BIGINT;
+
+UPDATE bank_accounts SET available_balance = balance;
+
+ALTER TABLE bank_accounts ALTER COLUMN available_balance SET NOT NULL;
+
+-- Only a pending transaction can change its status.
+CREATE OR REPLACE FUNCTION reject_transaction_rewrite() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE'
+        OR NEW.id <> OLD.id
+        OR NEW.amount <> OLD.amount
+        OR NEW.transaction_type <> OLD.transaction_type
+        OR NEW.origin_account_id <> OLD.origin_account_id
+        OR NEW.destination_account_id IS DISTINCT FROM OLD.destination_account_id
+        OR NEW.transfer_id IS DISTINCT FROM OLD.transfer_id
+        OR NEW.journal_entry_id <> OLD.journal_entry_id
+        OR NEW.external_id IS DISTINCT FROM OLD.external_id
+        OR NEW.reversal_of IS DISTINCT FROM OLD.reversal_of
+        OR NEW.created_at IS DISTINCT FROM OLD.created_at
+        OR (NEW.status <> OLD.status AND OLD.status <> 'PENDING') THEN
+        RAISE EXCEPTION 'Transaction % can''t be rewritten', OLD.id;
+    END IF;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,36 @@
+-- ISO 4217 currencies, with the number of decimal places of their minor
+-- unit. Amounts are stored in minor units of the currency of their account.
+CREATE TABLE currencies (
+  code VARCHAR(3),
+  exponent SMALLINT NOT NULL CHECK (exponent BETWEEN 0 AND 4),
+
+  CONSTRAINT "currencies_pkey" PRIMARY KEY ("code")
+);
+
+INSERT INTO currencies (code, exponent)
+VALUES
+  ('AED', 2), ('ARS', 2), ('AUD', 2), ('BGN', 2), ('BHD', 3), ('BRL', 2),
+  ('CAD', 2), ('CHF', 2), ('CLP', 0), ('CNY', 2), ('COP', 2), ('CZK', 2),
+  ('DKK', 2), ('EGP', 2), ('EUR', 2), ('GBP', 2), ('HKD', 2), ('HUF', 2),
+  ('IDR', 2), ('ILS', 2), ('INR', 2), ('ISK', 0), ('JOD', 3), ('JPY', 0),
+  ('KRW', 0), ('KWD', 3), ('MXN', 2), ('MYR', 2), ('NOK', 2), ('NZD', 2),
+  ('OMR', 3), ('PEN', 2), ('PHP', 2), ('PLN', 2), ('PYG', 0), ('RON', 2),
+  ('SAR', 2), ('SEK', 2), ('SGD', 2), ('THB', 2), ('TND', 3), ('TRY', 2),
+  ('TWD', 2), ('UAH', 2), ('USD', 2), ('UYU', 2), ('VND', 0), ('ZAR', 2);
+
+-- The accounts created before currencies were tracked are all in USD.
+ALTER TABLE bank_accounts
+  ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD' REFERENCES currencies(code);
+
+ALTER TABLE bank_accounts ALTER COLUMN currency DROP DEFAULT;
+
+-- A budget only counts the spending of the accounts in its currency, so a
+-- user can have one budget per currency on a category.
+ALTER TABLE budgets
+  ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD' REFERENCES currencies(code);
+
+ALTER TABLE budgets ALTER COLUMN currency DROP DEFAULT;
+
+DROP INDEX "budgets_user_id_category_id_key";
+
+CREATE UNIQUE INDEX "budgets_user_id_category_id_currency_key" ON "budgets"("user_id", "category_id", "currency");
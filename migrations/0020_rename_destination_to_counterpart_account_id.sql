@@ -0,0 +1,27 @@
+-- Both legs of a transfer point at the other account: the destination on
+-- the OUTCOME leg and the source on the INCOME leg.
+ALTER TABLE transactions RENAME COLUMN destination_account_id TO counterpart_account_id;
+
+ALTER TABLE transactions
+  RENAME CONSTRAINT "transactions_destination_account_id_fkey" TO "transactions_counterpart_account_id_fkey";
+
+CREATE OR REPLACE FUNCTION reject_transaction_rewrite() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE'
+        OR NEW.id <> OLD.id
+        OR NEW.amount <> OLD.amount
+        OR NEW.transaction_type <> OLD.transaction_type
+        OR NEW.origin_account_id <> OLD.origin_account_id
+        OR NEW.counterpart_account_id IS DISTINCT FROM OLD.counterpart_account_id
+        OR NEW.transfer_id IS DISTINCT FROM OLD.transfer_id
+        OR NEW.journal_entry_id <> OLD.journal_entry_id
+        OR NEW.external_id IS DISTINCT FROM OLD.external_id
+        OR NEW.reversal_of IS DISTINCT FROM OLD.reversal_of
+        OR NEW.created_at IS DISTINCT FROM OLD.created_at
+        OR (NEW.status <> OLD.status AND OLD.status <> 'PENDING') THEN
+        RAISE EXCEPTION 'Transaction % can''t be rewritten', OLD.id;
+    END IF;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,11 @@
+-- A category used by categorization rules or budgets can't be deleted,
+-- instead of silently deleting them along with it.
+ALTER TABLE categorization_rules
+  DROP CONSTRAINT "categorization_rules_category_id_fkey",
+  ADD CONSTRAINT "categorization_rules_category_id_fkey"
+    FOREIGN KEY (category_id) REFERENCES categories(id) ON DELETE RESTRICT;
+
+ALTER TABLE budgets
+  DROP CONSTRAINT "budgets_category_id_fkey",
+  ADD CONSTRAINT "budgets_category_id_fkey"
+    FOREIGN KEY (category_id) REFERENCES categories(id) ON DELETE RESTRICT;
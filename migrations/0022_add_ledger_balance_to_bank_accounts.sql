@@ -0,0 +1,43 @@
+-- Running total of the ledger postings of each account, kept by the
+-- database as postings are inserted. The service writes `balance` itself,
+-- so comparing both after every posting catches any drift between the
+-- balance and the ledger without summing the account history.
+ALTER TABLE bank_accounts ADD COLUMN ledger_balance BIGINT NOT NULL DEFAULT 0;
+
+UPDATE bank_accounts a
+SET ledger_balance = l.balance
+FROM ledger_account_balances l
+WHERE l.bank_account_id = a.id;
+
+CREATE FUNCTION add_postings_to_ledger_balance() RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE bank_accounts a
+    SET ledger_balance = a.ledger_balance + n.amount
+    FROM (
+        SELECT bank_account_id, SUM(amount) AS amount
+        FROM new_entries
+        WHERE bank_account_id IS NOT NULL
+        GROUP BY bank_account_id
+    ) n
+    WHERE a.id = n.bank_account_id;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "ledger_entries_ledger_balance"
+    AFTER INSERT ON ledger_entries
+    REFERENCING NEW TABLE AS new_entries
+    FOR EACH STATEMENT EXECUTE FUNCTION add_postings_to_ledger_balance();
+
+-- The running total only holds if postings are never changed; corrections
+-- are new journal entries.
+CREATE FUNCTION reject_ledger_entry_rewrite() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'Ledger entries can''t be updated or deleted';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "ledger_entries_immutable"
+    BEFORE UPDATE OR DELETE ON ledger_entries
+    FOR EACH ROW EXECUTE FUNCTION reject_ledger_entry_rewrite();
@@ -0,0 +1,10 @@
+-- Deleting a category set the category of its transactions to NULL, which
+-- rewrote posted transactions without recording a version. A category used
+-- by transactions can't be deleted anymore; they are recategorized with
+-- AmendTransaction first. Scheduled transactions keep ON DELETE SET NULL
+-- for the ended and cancelled ones; DeleteCategory refuses the categories
+-- of the active ones.
+ALTER TABLE transactions
+  DROP CONSTRAINT "transactions_category_id_fkey",
+  ADD CONSTRAINT "transactions_category_id_fkey"
+    FOREIGN KEY (category_id) REFERENCES categories(id) ON DELETE RESTRICT;
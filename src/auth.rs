@@ -0,0 +1,185 @@
+use std::env;
+
+use chrono::Utc;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tonic::{Request, Status};
+use uuid::Uuid;
+
+use crate::models::user::Role;
+
+const DEFAULT_TOKEN_TTL_SECONDS: i64 = 60 * 60;
+
+/// HS256 keys shorter than the 256 bits of the hash are easy to brute force.
+const MIN_SECRET_LENGTH: usize = 32;
+
+/// Example values from docs and env templates, which must never sign tokens.
+const PLACEHOLDER_SECRETS: [&str; 4] = ["change-me", "changeme", "secret", "jwt-secret"];
+
+#[derive(Error, Debug)]
+pub enum KeyError {
+    #[error("JWT_SECRET not present.")]
+    Missing,
+    #[error("JWT_SECRET is a placeholder, set it to a random secret")]
+    Placeholder,
+    #[error("JWT_SECRET must be at least {MIN_SECRET_LENGTH} bytes long")]
+    TooShort,
+}
+
+#[derive(Error, Debug)]
+pub enum TokenError {
+    #[error("Failed to sign the access token")]
+    Sign,
+    #[error("Missing authorization header")]
+    MissingHeader,
+    #[error("Malformed authorization header")]
+    MalformedHeader,
+    #[error("Invalid or expired access token")]
+    Invalid,
+    #[error("The access token has been revoked")]
+    Revoked,
+    #[error("Failed to check the access token")]
+    Lookup,
+}
+
+#[derive(Error, Debug)]
+pub enum AccessError {
+    #[error("Missing authenticated user")]
+    Unauthenticated,
+    #[error("The resource does not belong to the authenticated user")]
+    NotOwner,
+}
+
+impl From<AccessError> for Status {
+    fn from(err: AccessError) -> Self {
+        match err {
+            AccessError::Unauthenticated => Status::unauthenticated(err.to_string()),
+            AccessError::NotOwner => Status::permission_denied(err.to_string()),
+        }
+    }
+}
+
+/// Payload of the access tokens issued by `Login`. `sub` holds the user id.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub role: Role,
+    /// Must match `users.token_version` for the token to be accepted.
+    pub ver: i32,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// Identity of the caller, inserted into the request extensions by the
+/// `AuthorizationLayer` once the bearer token has been validated.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: Uuid,
+    pub role: Role,
+    pub token_version: i32,
+}
+
+impl AuthenticatedUser {
+    pub fn from_request<T>(request: &Request<T>) -> Result<AuthenticatedUser, AccessError> {
+        request
+            .extensions()
+            .get::<AuthenticatedUser>()
+            .cloned()
+            .ok_or(AccessError::Unauthenticated)
+    }
+
+    pub fn ensure_owns(&self, owner_id: &Uuid) -> Result<(), AccessError> {
+        if &self.user_id != owner_id {
+            return Err(AccessError::NotOwner);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct AccessToken {
+    pub value: String,
+    pub expires_in: i64,
+}
+
+/// HS256 keys used to sign and validate access tokens.
+pub struct TokenKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    ttl_seconds: i64,
+}
+
+impl TokenKeys {
+    pub fn new(secret: &[u8], ttl_seconds: i64) -> TokenKeys {
+        TokenKeys {
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            ttl_seconds,
+        }
+    }
+
+    /// Refuses to start with a missing, placeholder or short secret.
+    pub fn from_env() -> Result<TokenKeys, KeyError> {
+        let secret = env::var("JWT_SECRET").unwrap_or_default();
+        let secret = secret.trim();
+
+        if secret.is_empty() {
+            return Err(KeyError::Missing);
+        }
+
+        if PLACEHOLDER_SECRETS.contains(&secret.to_lowercase().as_str()) {
+            return Err(KeyError::Placeholder);
+        }
+
+        if secret.len() < MIN_SECRET_LENGTH {
+            return Err(KeyError::TooShort);
+        }
+
+        let ttl_seconds = env::var("ACCESS_TOKEN_TTL_SECONDS")
+            .ok()
+            .and_then(|raw| raw.parse().ok())
+            .unwrap_or(DEFAULT_TOKEN_TTL_SECONDS);
+
+        Ok(TokenKeys::new(secret.as_bytes(), ttl_seconds))
+    }
+
+    pub fn issue(
+        &self,
+        user_id: &str,
+        role: Role,
+        token_version: i32,
+    ) -> Result<AccessToken, TokenError> {
+        let now = Utc::now().timestamp();
+
+        let claims = Claims {
+            sub: user_id.to_owned(),
+            role,
+            ver: token_version,
+            iat: now,
+            exp: now + self.ttl_seconds,
+        };
+
+        let value = encode(&Header::new(Algorithm::HS256), &claims, &self.encoding)
+            .map_err(|_err| TokenError::Sign)?;
+
+        Ok(AccessToken {
+            value,
+            expires_in: self.ttl_seconds,
+        })
+    }
+
+    pub fn verify(&self, token: &str) -> Result<AuthenticatedUser, TokenError> {
+        let data = decode::<Claims>(token, &self.decoding, &Validation::new(Algorithm::HS256))
+            .map_err(|_err| TokenError::Invalid)?;
+
+        let user_id = Uuid::try_parse(&data.claims.sub).map_err(|_err| TokenError::Invalid)?;
+
+        Ok(AuthenticatedUser {
+            user_id,
+            role: data.claims.role,
+            token_version: data.claims.ver,
+        })
+    }
+}
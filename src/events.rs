@@ -0,0 +1,67 @@
+use tokio::sync::broadcast;
+
+use crate::models::budget::BudgetAlert;
+use crate::models::money::Money;
+use crate::models::transaction::Transaction;
+
+/// Number of events kept for subscribers that fall behind before they start
+/// missing some and have to resynchronize.
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub enum AccountEvent {
+    /// A transaction was recorded, as a hold when it is pending, or a
+    /// pending one was posted.
+    TransactionPosted {
+        transaction: Transaction,
+        balance: Money,
+        available_balance: Money,
+    },
+    /// A pending transaction was voided and its hold released.
+    TransactionVoided {
+        transaction: Transaction,
+        available_balance: Money,
+    },
+    /// Raised by a transaction posted on the account.
+    BudgetAlert {
+        account_id: String,
+        transaction_id: String,
+        alert: BudgetAlert,
+    },
+}
+
+impl AccountEvent {
+    pub fn account_id(&self) -> &str {
+        match self {
+            AccountEvent::TransactionPosted { transaction, .. }
+            | AccountEvent::TransactionVoided { transaction, .. } => &transaction.origin_account_id,
+            AccountEvent::BudgetAlert { account_id, .. } => account_id,
+        }
+    }
+}
+
+/// In-process fan-out of committed account changes to `WatchAccount`
+/// subscribers.
+#[derive(Debug, Clone)]
+pub struct AccountEvents {
+    sender: broadcast::Sender<AccountEvent>,
+}
+
+impl Default for AccountEvents {
+    fn default() -> Self {
+        let (sender, _receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        AccountEvents { sender }
+    }
+}
+
+impl AccountEvents {
+    /// Must only be called once the change has been committed.
+    pub fn publish(&self, event: AccountEvent) {
+        // Sending only fails when nobody is subscribed.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AccountEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -1,18 +1,42 @@
+use chrono::Utc;
 use sqlx::postgres::PgPool;
+use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
+use uuid::Uuid;
 
 use crate::proto::finance_control_server::FinanceControl;
 
+use crate::auth::{AuthenticatedUser, TokenKeys};
+use crate::events::{AccountEvent, AccountEvents};
 use crate::models::bank_account;
-use crate::models::transaction::{Transaction, TransactionType};
-use crate::models::user::{User, UserError};
+use crate::models::budget::{self, Budget, BudgetError, BudgetStatus, Month};
+use crate::models::category::{Category, CategoryError};
+use crate::models::currency::{Currency, CurrencyError, DEFAULT_CURRENCY};
+use crate::models::idempotency::IdempotencyKey;
+use crate::models::ledger::{self, JournalEntry};
+use crate::models::money::{Money, MoneyError};
+use crate::models::report::{self, AccountActivity, CategoryActivity, Period};
+use crate::models::rule::{Rule, RuleError, RuleSet};
+use crate::models::schedule::{self, Frequency, Recurrence, ScheduleError, ScheduledTransaction};
+use crate::models::tag;
+use crate::models::transaction::{
+    Transaction, TransactionFilter, TransactionStatus, TransactionType, TransactionVersion,
+};
+use crate::models::user::{Password, User, UserError};
+use crate::pagination::{self, PageToken};
+use crate::posting::{self, validate_transaction_input, PostingError};
 use crate::proto;
+use crate::statement::{self, ExportFormat, StatementError, StatementWriter};
 use crate::tracing::{error, info};
 
 pub struct FinanceControlService {
     pub state: Arc<tokio::sync::RwLock<u64>>,
     pub db_pool: Arc<PgPool>,
+    pub token_keys: Arc<TokenKeys>,
+    pub events: AccountEvents,
 }
 
 impl FinanceControlService {
@@ -23,8 +47,484 @@ impl FinanceControlService {
     }
 }
 
+impl From<Transaction> for proto::Transaction {
+    fn from(transaction: Transaction) -> Self {
+        proto::Transaction {
+            id: transaction.id,
+            account_id: transaction.origin_account_id,
+            amount_minor: transaction.amount.to_wire(),
+            transaction_type: transaction.transaction_type.to_proto(),
+            status: transaction.status.to_proto(),
+            description: transaction.description,
+            destination_account_id: transaction.destination_account_id,
+            transfer_id: transaction.transfer_id,
+            created_at: pagination::format_timestamp(&transaction.created_at),
+            external_id: transaction.external_id,
+            category_id: transaction.category_id,
+            tags: transaction.tags,
+            reversal_of: transaction.reversal_of,
+            reversed_by: transaction.reversed_by,
+            version: transaction.version,
+        }
+    }
+}
+
+impl From<TransactionVersion> for proto::TransactionVersion {
+    fn from(version: TransactionVersion) -> Self {
+        proto::TransactionVersion {
+            version: version.version,
+            description: version.description,
+            category_id: version.category_id,
+            amendment_reason: version.amendment_reason,
+            superseded_at: pagination::format_timestamp(&version.superseded_at),
+        }
+    }
+}
+
+/// Buffered events per `WatchAccount` stream before the forwarding task
+/// waits for the client to catch up.
+const WATCH_BUFFER_SIZE: usize = 32;
+
+impl From<AccountEvent> for proto::AccountEvent {
+    fn from(event: AccountEvent) -> Self {
+        match event {
+            AccountEvent::TransactionPosted {
+                transaction,
+                balance,
+                available_balance,
+            } => proto::AccountEvent {
+                event: Some(proto::account_event::Event::TransactionPosted(Box::new(
+                    proto::TransactionPosted {
+                        transaction: Some(transaction.into()),
+                        balance_minor: balance.to_wire(),
+                        available_balance_minor: available_balance.to_wire(),
+                    },
+                ))),
+            },
+            AccountEvent::TransactionVoided {
+                transaction,
+                available_balance,
+            } => proto::AccountEvent {
+                event: Some(proto::account_event::Event::TransactionVoided(Box::new(
+                    proto::TransactionVoided {
+                        transaction: Some(transaction.into()),
+                        available_balance_minor: available_balance.to_wire(),
+                    },
+                ))),
+            },
+            AccountEvent::BudgetAlert {
+                account_id,
+                transaction_id,
+                alert,
+            } => proto::AccountEvent {
+                event: Some(proto::account_event::Event::BudgetAlert(
+                    proto::BudgetAlert {
+                        account_id,
+                        transaction_id,
+                        budget: Some(alert.status.into()),
+                        threshold_percent: alert.threshold_percent,
+                    },
+                )),
+            },
+        }
+    }
+}
+
+fn balance_snapshot(account: &bank_account::BankAccount) -> proto::AccountEvent {
+    proto::AccountEvent {
+        event: Some(proto::account_event::Event::Snapshot(
+            proto::BalanceSnapshot {
+                account_id: account.id.to_string(),
+                balance_minor: account.balance.to_wire(),
+                available_balance_minor: account.available_balance.to_wire(),
+            },
+        )),
+    }
+}
+
+impl From<User> for proto::User {
+    fn from(user: User) -> Self {
+        proto::User {
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            role: user.role.to_string(),
+            created_at: user.created_at,
+        }
+    }
+}
+
+impl From<Category> for proto::Category {
+    fn from(category: Category) -> Self {
+        proto::Category {
+            id: category.id.to_string(),
+            parent_id: category.parent_id.map(|id| id.to_string()),
+            system: category.is_system(),
+            name: category.name,
+            created_at: category.created_at,
+        }
+    }
+}
+
+impl From<Rule> for proto::Rule {
+    fn from(rule: Rule) -> Self {
+        proto::Rule {
+            id: rule.id.to_string(),
+            definition: Some(proto::RuleDefinition {
+                priority: rule.priority,
+                description_contains: rule.description_contains,
+                description_pattern: rule.description_pattern,
+                min_amount_minor: rule.min_amount.map(Money::to_wire),
+                max_amount_minor: rule.max_amount.map(Money::to_wire),
+                account_id: rule.account_id.map(|id| id.to_string()),
+                transaction_type: rule
+                    .transaction_type
+                    .map(|transaction_type| transaction_type.to_proto()),
+                category_id: rule.category_id.map(|id| id.to_string()),
+                tags: rule.tags,
+            }),
+            created_at: rule.created_at,
+        }
+    }
+}
+
+impl From<Budget> for proto::Budget {
+    fn from(budget: Budget) -> Self {
+        proto::Budget {
+            id: budget.id.to_string(),
+            category_id: budget.category_id.to_string(),
+            limit_minor: budget.limit.to_wire(),
+            created_at: budget.created_at,
+            updated_at: budget.updated_at,
+            currency: budget.currency,
+        }
+    }
+}
+
+impl From<BudgetStatus> for proto::BudgetStatus {
+    fn from(status: BudgetStatus) -> Self {
+        proto::BudgetStatus {
+            month: status.month.to_string(),
+            spent_minor: status.spent.to_wire(),
+            remaining_minor: status.remaining().to_wire(),
+            percent_used: status.percent_used(),
+            budget: Some(status.budget.into()),
+        }
+    }
+}
+
+impl From<ScheduledTransaction> for proto::ScheduledTransaction {
+    fn from(schedule: ScheduledTransaction) -> Self {
+        proto::ScheduledTransaction {
+            id: schedule.id.to_string(),
+            transaction: Some(proto::ExecuteTransactionRequest {
+                account_id: schedule.account_id.to_string(),
+                transaction_type: schedule.transaction_type.to_proto(),
+                description: schedule.description,
+                amount_minor: schedule.amount.to_wire(),
+                category_id: schedule.category_id.map(|id| id.to_string()),
+                tags: schedule.tags,
+                pending: false,
+                currency: String::new(),
+                occurred_at: None,
+            }),
+            starts_at: schedule.starts_at.to_string(),
+            recurrence: Some(proto::Recurrence {
+                frequency: schedule.recurrence.frequency.to_proto(),
+                interval: schedule.recurrence.interval,
+                day_of_month: schedule.recurrence.day_of_month,
+                ends_at: schedule
+                    .recurrence
+                    .ends_at
+                    .map(|ends_at| ends_at.to_string()),
+                count: schedule.recurrence.max_occurrences,
+            }),
+            occurrences: schedule.occurrences,
+            next_occurrence_at: schedule.next_occurrence_at.map(|next| next.to_string()),
+            last_error: schedule.last_error,
+            cancelled_at: schedule.cancelled_at,
+            created_at: schedule.created_at,
+        }
+    }
+}
+
+impl From<bank_account::BankAccount> for proto::BankAccount {
+    fn from(account: bank_account::BankAccount) -> Self {
+        proto::BankAccount {
+            id: account.id.to_string(),
+            name: account.name,
+            account_type: account.account_type.to_string(),
+            balance_minor: account.balance.to_wire(),
+            created_at: account.created_at,
+            available_balance_minor: account.available_balance.to_wire(),
+            currency: account.currency.code,
+            currency_exponent: account.currency.exponent as i32,
+        }
+    }
+}
+
+/// Builds a rule of the user from its definition. Whether the account and
+/// the category can be used by the user is checked against the database
+/// afterwards.
+fn rule_from_definition(user_id: Uuid, definition: &proto::RuleDefinition) -> Result<Rule, String> {
+    let mut rule = Rule::new(user_id, definition.priority);
+
+    rule.description_contains = definition
+        .description_contains
+        .as_deref()
+        .map(|contains| contains.trim().to_owned());
+    rule.description_pattern = definition.description_pattern.clone();
+
+    rule.min_amount = definition
+        .min_amount_minor
+        .map(Money::from_wire)
+        .transpose()
+        .map_err(|err| err.to_string())?;
+    rule.max_amount = definition
+        .max_amount_minor
+        .map(Money::from_wire)
+        .transpose()
+        .map_err(|err| err.to_string())?;
+
+    rule.account_id = definition
+        .account_id
+        .as_deref()
+        .map(Uuid::try_parse)
+        .transpose()
+        .map_err(|_err| "Bank account not found".to_owned())?;
+    rule.transaction_type = definition
+        .transaction_type
+        .as_ref()
+        .map(TransactionType::from_proto)
+        .transpose()?;
+    rule.category_id = definition
+        .category_id
+        .as_deref()
+        .map(Uuid::try_parse)
+        .transpose()
+        .map_err(|_err| CategoryError::NotFound.to_string())?;
+
+    rule.tags = definition
+        .tags
+        .iter()
+        .map(|raw| tag::normalize(raw))
+        .collect::<Result<Vec<_>, _>>()?;
+    rule.tags.sort();
+    rule.tags.dedup();
+
+    rule.validate().map_err(|err| err.to_string())?;
+
+    Ok(rule)
+}
+
+const DEFAULT_RULE_TEST_MATCHES: i32 = 20;
+const MAX_RULE_TEST_MATCHES: i32 = 100;
+
+/// How many of the most recent transactions of the user `TestRule` runs
+/// the rule against.
+const RULE_TEST_SCAN_SIZE: i64 = 5_000;
+
+const APPLY_RULES_PAGE_SIZE: i64 = 1_000;
+
+/// Length limit of the free text columns, stored as VARCHAR(255).
+const MAX_TEXT_LENGTH: usize = 255;
+
+fn is_valid_text(text: &str) -> bool {
+    !text.is_empty() && text.chars().count() <= MAX_TEXT_LENGTH
+}
+
+const IMPORT_MODE_HEADER: &str = "import-mode";
+
+/// Upper bound on the lines of a single `ImportTransactions` stream, as the
+/// whole import runs in one DB transaction.
+const MAX_IMPORT_LINES: usize = 50_000;
+
+fn import_error(line: usize, message: impl Into<String>) -> proto::ImportError {
+    proto::ImportError {
+        line: line as i32,
+        message: message.into(),
+    }
+}
+
+/// Folds the per-account activity into the buckets of the user, one per
+/// period and currency, carrying every account balance forward so the
+/// ending balance of a bucket also counts the accounts in its currency
+/// without transactions in it.
+fn summary_buckets(
+    opening_balances: Vec<(String, String, Money)>,
+    activity: Vec<AccountActivity>,
+    category_activity: Vec<CategoryActivity>,
+) -> Result<Vec<proto::SummaryBucket>, MoneyError> {
+    // Keyed by currency, then account.
+    let mut balances: HashMap<(String, String), Money> = opening_balances
+        .into_iter()
+        .map(|(account_id, currency, balance)| ((currency, account_id), balance))
+        .collect();
+    let mut buckets = Vec::new();
+
+    let mut categories: HashMap<(String, String), Vec<proto::CategorySummary>> = HashMap::new();
+
+    for row in category_activity {
+        categories
+            .entry((row.bucket, row.currency))
+            .or_default()
+            .push(proto::CategorySummary {
+                category_id: row.category_id,
+                income_minor: row.income.to_wire(),
+                outcome_minor: row.outcome.to_wire(),
+                transaction_count: row.transaction_count as i32,
+            });
+    }
+
+    for rows in activity
+        .chunk_by(|left, right| left.bucket == right.bucket && left.currency == right.currency)
+    {
+        let currency = &rows[0].currency;
+        let mut income = Money::default();
+        let mut outcome = Money::default();
+        let mut accounts = Vec::with_capacity(rows.len());
+
+        for row in rows {
+            income = income.checked_add(row.external_income)?;
+            outcome = outcome.checked_add(row.external_outcome)?;
+
+            let balance = balances
+                .entry((row.currency.clone(), row.account_id.clone()))
+                .or_default();
+            *balance = balance.checked_add(row.net_change)?;
+
+            accounts.push(proto::AccountSummary {
+                account_id: row.account_id.clone(),
+                income_minor: row.income.to_wire(),
+                outcome_minor: row.outcome.to_wire(),
+                net_change_minor: row.net_change.to_wire(),
+                ending_balance_minor: balance.to_wire(),
+            });
+        }
+
+        let ending_balance = balances
+            .iter()
+            .filter(|((balance_currency, _), _)| balance_currency == currency)
+            .try_fold(Money::default(), |total, (_, balance)| {
+                total.checked_add(*balance)
+            })?;
+
+        buckets.push(proto::SummaryBucket {
+            period_start: rows[0].bucket.clone(),
+            income_minor: income.to_wire(),
+            outcome_minor: outcome.to_wire(),
+            net_change_minor: income.checked_sub(outcome)?.to_wire(),
+            ending_balance_minor: ending_balance.to_wire(),
+            accounts,
+            categories: categories
+                .remove(&(rows[0].bucket.clone(), currency.clone()))
+                .unwrap_or_default(),
+            currency: currency.clone(),
+        });
+    }
+
+    Ok(buckets)
+}
+
+/// Transactions per `ExportStatement` chunk.
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// Buffered chunks per `ExportStatement` stream.
+const EXPORT_BUFFER_SIZE: usize = 4;
+
+/// Sends the statement of the account, read from a single snapshot so the
+/// balances always agree with the transactions listed. Stops quietly when
+/// the client goes away.
+async fn send_statement(
+    db_pool: &PgPool,
+    sender: &mpsc::Sender<Result<proto::StatementChunk, Status>>,
+    account: bank_account::BankAccount,
+    format: ExportFormat,
+    from: Option<String>,
+    to: String,
+) -> Result<(), StatementError> {
+    let mut txn = db_pool.begin().await?;
+
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *txn)
+        .await?;
+
+    let opening_balance = match from.as_deref() {
+        Some(from) => ledger::balance_before(&mut *txn, account.id, from).await?,
+        None => Money::default(),
+    };
+
+    let mut writer = StatementWriter::new(format, &account, from.as_deref(), &to, opening_balance)?;
+
+    if sender
+        .send(Ok(proto::StatementChunk {
+            data: writer.header()?,
+        }))
+        .await
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    let mut filter = TransactionFilter {
+        account_id: account.id,
+        from,
+        to: Some(to),
+        transaction_type: None,
+        status: Some(TransactionStatus::POSTED),
+        min_amount: None,
+        max_amount: None,
+        after: None,
+        limit: EXPORT_PAGE_SIZE,
+    };
+
+    loop {
+        let transactions = Transaction::list(&mut *txn, &filter).await?;
+
+        let Some(last) = transactions.last() else {
+            break;
+        };
+
+        filter.after = Some(PageToken {
+            created_at: last.created_at.clone(),
+            id: last.id.clone(),
+        });
+
+        if sender
+            .send(Ok(proto::StatementChunk {
+                data: writer.transactions(&transactions)?,
+            }))
+            .await
+            .is_err()
+        {
+            return Ok(());
+        }
+
+        if (transactions.len() as i64) < EXPORT_PAGE_SIZE {
+            break;
+        }
+    }
+
+    if sender
+        .send(Ok(proto::StatementChunk {
+            data: writer.footer()?,
+        }))
+        .await
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    txn.commit().await?;
+
+    Ok(())
+}
+
 #[tonic::async_trait]
 impl FinanceControl for FinanceControlService {
+    type WatchAccountStream = ReceiverStream<Result<proto::AccountEvent, Status>>;
+    type ExportStatementStream = ReceiverStream<Result<proto::StatementChunk, Status>>;
+
     async fn register_user(
         &self,
         request: Request<proto::RegisterUserRequest>,
@@ -47,17 +547,20 @@ impl FinanceControl for FinanceControlService {
             ));
         }
 
-        let user = User::new(input.name, input.email, input.password)
-            .map_err(|err| Status::internal(err.to_string()))?;
+        let user = User::new(input.name, input.email, input.password).map_err(|err| match err {
+            UserError::InvalidPassword => Status::invalid_argument(err.to_string()),
+            _ => Status::internal(err.to_string()),
+        })?;
 
         let query =
-          "INSERT INTO users (id, name, email, password, created_at) VALUES ($1::uuid, $2, $3, $4, $5::timestamp)";
+          "INSERT INTO users (id, name, email, password, role, created_at) VALUES ($1::uuid, $2, $3, $4, $5, $6::timestamp)";
 
         sqlx::query(query)
             .bind(&user.id)
             .bind(&user.name)
             .bind(&user.email)
             .bind(&user.password.value)
+            .bind(user.role)
             .bind(&user.created_at)
             .execute(self.db_pool.as_ref())
             .await
@@ -71,6 +574,187 @@ impl FinanceControl for FinanceControlService {
         Ok(Response::new(response))
     }
 
+    async fn login(
+        &self,
+        request: Request<proto::LoginRequest>,
+    ) -> Result<Response<proto::LoginResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a login request.");
+
+        let input = request.into_inner();
+
+        let user = User::find_by_email(self.db_pool.as_ref(), &input.email)
+            .await
+            .map_err(|err| {
+                error!("Error while finding the user {:?}", err);
+                Status::internal("Internal server error")
+            })?
+            .ok_or_else(|| Status::unauthenticated(UserError::InvalidCredentials.to_string()))?;
+
+        user.password
+            .verify(&input.password)
+            .map_err(|err| match err {
+                UserError::InvalidCredentials => Status::unauthenticated(err.to_string()),
+                _ => Status::internal(err.to_string()),
+            })?;
+
+        let token = self
+            .token_keys
+            .issue(&user.id, user.role, user.token_version)
+            .map_err(|err| {
+                error!("Error while issuing the access token {:?}", err);
+                Status::internal("Internal server error")
+            })?;
+
+        let response = proto::LoginResponse {
+            access_token: token.value,
+            token_type: "Bearer".to_owned(),
+            expires_in: token.expires_in,
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_user(
+        &self,
+        request: Request<proto::GetUserRequest>,
+    ) -> Result<Response<proto::GetUserResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a get user request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let user = User::find_by_id(self.db_pool.as_ref(), user_id)
+            .await
+            .map_err(|err| {
+                error!("Error while finding the user {:?}", err);
+                Status::internal("Internal server error")
+            })?
+            .ok_or_else(|| Status::not_found("User not found".to_owned()))?;
+
+        let response = proto::GetUserResponse {
+            user: Some(user.into()),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn update_user(
+        &self,
+        request: Request<proto::UpdateUserRequest>,
+    ) -> Result<Response<proto::UpdateUserResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received an update user request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        User::update_profile(
+            self.db_pool.as_ref(),
+            user_id,
+            input.name.as_deref(),
+            input.email.as_deref(),
+        )
+        .await
+        .map_err(|err| match err.as_database_error() {
+            Some(db_err) if db_err.is_unique_violation() => {
+                Status::invalid_argument(UserError::EmailAlreadyInUse.to_string())
+            }
+            _ => {
+                error!("Error while updating the user {:?}", err);
+                Status::internal("Internal server error")
+            }
+        })?;
+
+        let user = User::find_by_id(self.db_pool.as_ref(), user_id)
+            .await
+            .map_err(|err| {
+                error!("Error while finding the user {:?}", err);
+                Status::internal("Internal server error")
+            })?
+            .ok_or_else(|| Status::not_found("User not found".to_owned()))?;
+
+        let response = proto::UpdateUserResponse {
+            user: Some(user.into()),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn change_password(
+        &self,
+        request: Request<proto::ChangePasswordRequest>,
+    ) -> Result<Response<proto::ChangePasswordResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a change password request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let mut password = Password::from_raw(input.new_password)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let user = User::find_by_id(self.db_pool.as_ref(), user_id)
+            .await
+            .map_err(|err| {
+                error!("Error while finding the user {:?}", err);
+                Status::internal("Internal server error")
+            })?
+            .ok_or_else(|| Status::not_found("User not found".to_owned()))?;
+
+        user.password
+            .verify(&input.current_password)
+            .map_err(|err| match err {
+                UserError::InvalidCredentials => {
+                    Status::permission_denied(UserError::IncorrectPassword.to_string())
+                }
+                _ => Status::internal(err.to_string()),
+            })?;
+
+        password
+            .get_hashed_value()
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        let token_version = User::update_password(self.db_pool.as_ref(), &user.id, &password)
+            .await
+            .map_err(|err| {
+                error!("Error while updating the password {:?}", err);
+                Status::internal("Internal server error")
+            })?;
+
+        let token = self
+            .token_keys
+            .issue(&user.id, user.role, token_version)
+            .map_err(|err| {
+                error!("Error while issuing the access token {:?}", err);
+                Status::internal("Internal server error")
+            })?;
+
+        let response = proto::ChangePasswordResponse {
+            access_token: token.value,
+            token_type: "Bearer".to_owned(),
+            expires_in: token.expires_in,
+        };
+
+        Ok(Response::new(response))
+    }
+
     async fn create_bank_account(
         &self,
         request: Request<proto::CreateBankAccountRequest>,
@@ -78,48 +762,117 @@ impl FinanceControl for FinanceControlService {
         self.incremet_counter().await;
         info!("Received a bank account creation request.");
 
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let idempotency_key =
+            IdempotencyKey::from_request(&request, caller.user_id, "CreateBankAccount")?;
         let input = request.into_inner();
 
-        let user_exists_query = "SELECT * FROM users WHERE id::text = $1";
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
 
-        let _ = sqlx::query(user_exists_query)
-            .bind(&input.user_id)
-            .fetch_one(self.db_pool.as_ref())
+        caller.ensure_owns(&user_id)?;
+
+        User::find_by_id(self.db_pool.as_ref(), user_id)
             .await
-            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+            .map_err(|err| {
+                error!("Error while finding the user {:?}", err);
+                Status::internal("Internal server error")
+            })?
+            .ok_or_else(|| Status::invalid_argument("User not found".to_owned()))?;
+
+        let account_type = bank_account::AccountType::from_raw_string(input.account_type.as_str())
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
 
-        let account_type = bank_account::AccountType::from_raw_string(&input.account_type.as_str())
+        let initial_balance = Money::from_wire(input.initial_balance_minor)
             .map_err(|err| Status::invalid_argument(err.to_string()))?;
 
+        let currency_code = Currency::normalize_code(&input.currency, DEFAULT_CURRENCY)?;
+
+        let currency = Currency::find(self.db_pool.as_ref(), &currency_code)
+            .await
+            .map_err(CurrencyError::from)?
+            .ok_or(CurrencyError::Unsupported)?;
+
         let account = bank_account::BankAccount::new(
             input.name,
-            input.initial_balance * 100.0,
+            initial_balance,
+            currency,
             account_type,
             input.user_id,
         )
         .map_err(|err| Status::invalid_argument(err.to_string()))?;
 
+        let mut txn = self.db_pool.as_ref().begin().await.map_err(|err| {
+            error!("Error while starting DB transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        if let Some(key) = &idempotency_key {
+            if let Some(response) = key.claim(&mut txn).await? {
+                info!("Replaying the response of a repeated bank account creation.");
+                return Ok(Response::new(response));
+            }
+        }
+
         let insert_bank_account_query =
-      "INSERT INTO bank_accounts (id, name, balance, type, user_id, created_at) VALUES ($1::uuid, $2, $3, $4::bankaccounttype, $5::uuid, $6::timestamp)";
+      "INSERT INTO bank_accounts (id, name, balance, available_balance, currency, type, user_id, created_at) VALUES ($1::uuid, $2, $3, $4, $5, $6::bankaccounttype, $7::uuid, $8::timestamp)";
 
         sqlx::query(insert_bank_account_query)
-            .bind(&account.id)
+            .bind(account.id)
             .bind(&account.name)
-            .bind(&account.balance)
-            .bind(&account.account_type.to_string())
-            .bind(&account.user_id)
+            .bind(account.balance)
+            .bind(account.available_balance)
+            .bind(&account.currency.code)
+            .bind(account.account_type.to_string())
+            .bind(account.user_id)
             .bind(&account.created_at)
-            .execute(self.db_pool.as_ref())
+            .execute(&mut *txn)
             .await
             .map_err(|err| {
                 error!("Error while creating a bank account {:?}", err);
                 Status::internal("Internal server error")
             })?;
 
+        // The opening balance is recorded as a transaction so every balance
+        // is the sum of its account's transactions.
+        if !account.balance.is_zero() {
+            let opening = Transaction::new(
+                account.balance,
+                TransactionType::INCOME,
+                account.id.to_string(),
+                Some("Opening balance".to_owned()),
+            );
+
+            JournalEntry::for_transaction(&opening)
+                .insert(&mut txn)
+                .await
+                .map_err(|err| {
+                    error!(
+                        "Error while posting opening balance to the ledger: {:?}",
+                        err
+                    );
+                    Status::internal("Internal server error")
+                })?;
+
+            opening.insert(&mut txn).await.map_err(|err| {
+                error!("Error while inserting opening balance: {:?}", err);
+                Status::internal("Internal server error")
+            })?;
+        }
+
         let response = proto::CreateBankAccountResponse {
             account_id: account.id.to_string(),
         };
 
+        if let Some(key) = &idempotency_key {
+            key.complete(&mut txn, &response).await?;
+        }
+
+        txn.commit().await.map_err(|err| {
+            error!("Failed to commit bank account creation: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
         Ok(Response::new(response))
     }
 
@@ -130,101 +883,2047 @@ impl FinanceControl for FinanceControlService {
         self.incremet_counter().await;
         info!("Received a execute transaction request.");
 
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let idempotency_key =
+            IdempotencyKey::from_request(&request, caller.user_id, "ExecuteTransaction")?;
         let input = request.into_inner();
 
-        let mut account = sqlx::query(
-            r#"SELECT id, name, balance, type, user_id, created_at::text
-               FROM bank_accounts 
-               WHERE id::text = $1"#,
-        )
-        .bind(&input.account_id)
-        .map(|row| bank_account::BankAccount::from_pg_row(row))
-        .fetch_one(self.db_pool.as_ref())
-        .await
-        .and_then(|result| {
-            result.map_err(|err| {
-                error!("Error finding bank account: {:?}", err);
-
-                sqlx::Error::RowNotFound
-            })
-        })
-        .map_err(|err| match err {
-            sqlx::Error::RowNotFound => {
-                Status::invalid_argument("Bank account not found".to_owned())
-            }
-            _ => Status::internal("Internal server error".to_owned()),
-        })?;
+        let account_id =
+            Uuid::try_parse(&input.account_id).map_err(|_err| PostingError::AccountNotFound)?;
 
-        let transaction_type = TransactionType::from_proto(&input.transaction_type)
-            .map_err(|err| Status::invalid_argument(err))?;
+        let transaction_input =
+            validate_transaction_input(&input).map_err(Status::invalid_argument)?;
 
-        if transaction_type == TransactionType::OUTCOME && account.balance < input.amount {
+        if transaction_input.occurred_at.is_some() {
             return Err(Status::invalid_argument(
-                "The account does not have enough funds for the transfer".to_owned(),
+                "Only imported transactions can set occurred_at".to_owned(),
             ));
         }
 
-        let transaction = Transaction::new(
-            input.amount,
-            transaction_type,
-            input.account_id,
-            input.description,
-        );
-
-        let _new_balance_result = account
-            .update_balance(&transaction)
-            .map_err(|err| Status::invalid_argument(err.to_string()))?;
-
         let mut txn = self.db_pool.as_ref().begin().await.map_err(|err| {
             error!("Error while starting DB transaction: {:?}", err);
             Status::internal("Internal server error".to_owned())
         })?;
 
-        let insert_transaction_query = r#"INSERT INTO transactions (id, amount, transaction_type, origin_account_id, description, created_at) VALUES ($1::uuid, $2, $3::transactiontype, $4::uuid, $5, $6::timestamp)"#;
+        if let Some(key) = &idempotency_key {
+            if let Some(response) = key.claim(&mut txn).await? {
+                info!("Replaying the response of a repeated transaction.");
+                return Ok(Response::new(response));
+            }
+        }
 
-        let amount_to_save = transaction.amount * 100.0;
+        let posted = posting::post_transaction(
+            &mut txn,
+            caller.user_id,
+            account_id,
+            transaction_input,
+            input.description,
+        )
+        .await?;
 
-        let _transaction_result = sqlx::query(insert_transaction_query)
-            .bind(&transaction.id.to_string())
-            .bind(&amount_to_save)
-            .bind(&transaction.transaction_type.to_string())
-            .bind(&transaction.origin_account_id.to_string())
-            .bind(&transaction.description)
-            .bind(&transaction.created_at)
-            .execute(&mut *txn)
-            .await
-            .map_err(|err| {
-                error!("Error while inserting transaction: {:?}", err);
-                Status::internal("Internal server error".to_owned())
-            })?;
+        let response = proto::ExecuteTransactionResponse {
+            transaction_id: posted.transaction.id.clone(),
+        };
 
-        let update_account_balance_query = r#"
-            UPDATE bank_accounts
-            SET balance = $1
-            WHERE id = $2::uuid
-        "#;
+        if let Some(key) = &idempotency_key {
+            key.complete(&mut txn, &response).await?;
+        }
 
-        let updated_balance = (account.balance * 100.0) as i64;
+        txn.commit().await.map_err(|err| {
+            error!("Failed to commit insert transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
 
-        sqlx::query(update_account_balance_query)
-            .bind(&updated_balance)
-            .bind(&account.id)
-            .execute(&mut *txn)
+        posted.publish(&self.events);
+
+        Ok(Response::new(response))
+    }
+
+    async fn transfer_funds(
+        &self,
+        request: Request<proto::TransferFundsRequest>,
+    ) -> Result<Response<proto::TransferFundsResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a transfer funds request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let from_account_id = Uuid::try_parse(&input.from_account_id)
+            .map_err(|_err| Status::invalid_argument("Bank account not found".to_owned()))?;
+        let to_account_id = Uuid::try_parse(&input.to_account_id)
+            .map_err(|_err| Status::invalid_argument("Bank account not found".to_owned()))?;
+
+        if from_account_id == to_account_id {
+            return Err(Status::invalid_argument(
+                "The source and destination accounts must be different".to_owned(),
+            ));
+        }
+
+        let amount = Money::from_wire(input.amount_minor)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        if amount.is_zero() {
+            return Err(Status::invalid_argument(
+                "The amount must be greater than zero".to_owned(),
+            ));
+        }
+
+        let mut txn = self.db_pool.as_ref().begin().await.map_err(|err| {
+            error!("Error while starting DB transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        // Always lock the lower id first so two opposite transfers between
+        // the same accounts can't deadlock.
+        let mut lock_order = [from_account_id, to_account_id];
+        lock_order.sort();
+
+        let mut locked = Vec::with_capacity(lock_order.len());
+
+        for account_id in lock_order {
+            let account = bank_account::BankAccount::find_for_update(&mut txn, account_id)
+                .await
+                .map_err(|err| {
+                    error!("Error finding bank account: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?
+                .ok_or_else(|| Status::invalid_argument("Bank account not found".to_owned()))?;
+
+            caller.ensure_owns(&account.user_id)?;
+
+            locked.push(account);
+        }
+
+        let mut to_account = locked.pop().unwrap();
+        let mut from_account = locked.pop().unwrap();
+
+        if from_account.id != from_account_id {
+            std::mem::swap(&mut from_account, &mut to_account);
+        }
+
+        if from_account.currency != to_account.currency {
+            return Err(CurrencyError::CrossCurrencyTransfer.into());
+        }
+
+        from_account.currency.ensure_matches(&input.currency)?;
+
+        let (debit, credit) = Transaction::transfer(
+            amount,
+            from_account.id.to_string(),
+            to_account.id.to_string(),
+            input.description,
+        );
+
+        from_account
+            .update_balance(&debit)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        to_account
+            .update_balance(&credit)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        JournalEntry::for_transfer(&debit, &credit)
+            .insert(&mut txn)
             .await
             .map_err(|err| {
+                error!("Error while posting transfer to the ledger: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        for transaction in [&debit, &credit] {
+            transaction.insert(&mut txn).await.map_err(|err| {
+                error!("Error while inserting transaction: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+        }
+
+        for account in [&from_account, &to_account] {
+            account.save_balance(&mut txn).await.map_err(|err| {
                 error!("Error while updating account balance: {:?}", err);
                 Status::internal("Internal server error".to_owned())
             })?;
 
+            ledger::verify_balance(&mut txn, account)
+                .await
+                .map_err(|err| {
+                    error!("Error while verifying account balance: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?;
+        }
+
         txn.commit().await.map_err(|err| {
-            error!("Failed to commit insert transaction: {:?}", err);
+            error!("Failed to commit transfer: {:?}", err);
             Status::internal("Internal server error".to_owned())
         })?;
 
-        let response = proto::ExecuteTransactionResponse {
-            transaction_id: transaction.id,
+        let response = proto::TransferFundsResponse {
+            transfer_id: debit.transfer_id.clone().unwrap_or_default(),
+            debit_transaction_id: debit.id.clone(),
+            credit_transaction_id: credit.id.clone(),
+        };
+
+        self.events.publish(AccountEvent::TransactionPosted {
+            transaction: debit,
+            balance: from_account.balance,
+            available_balance: from_account.available_balance,
+        });
+        self.events.publish(AccountEvent::TransactionPosted {
+            transaction: credit,
+            balance: to_account.balance,
+            available_balance: to_account.available_balance,
+        });
+
+        Ok(Response::new(response))
+    }
+
+    async fn list_transactions(
+        &self,
+        request: Request<proto::ListTransactionsRequest>,
+    ) -> Result<Response<proto::ListTransactionsResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a list transactions request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let account_id = Uuid::try_parse(&input.account_id)
+            .map_err(|_err| Status::invalid_argument("Bank account not found".to_owned()))?;
+
+        let account = bank_account::BankAccount::find(self.db_pool.as_ref(), account_id)
+            .await
+            .map_err(|err| {
+                error!("Error finding bank account: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?
+            .ok_or_else(|| Status::invalid_argument("Bank account not found".to_owned()))?;
+
+        caller.ensure_owns(&account.user_id)?;
+
+        let from = input
+            .from
+            .as_deref()
+            .map(pagination::parse_timestamp)
+            .transpose()
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let to = input
+            .to
+            .as_deref()
+            .map(pagination::parse_timestamp)
+            .transpose()
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let transaction_type = input
+            .transaction_type
+            .as_ref()
+            .map(TransactionType::from_proto)
+            .transpose()
+            .map_err(Status::invalid_argument)?;
+
+        let status = input
+            .status
+            .as_ref()
+            .map(TransactionStatus::from_proto)
+            .transpose()
+            .map_err(Status::invalid_argument)?;
+
+        let min_amount = input
+            .min_amount_minor
+            .map(Money::from_wire)
+            .transpose()
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let max_amount = input
+            .max_amount_minor
+            .map(Money::from_wire)
+            .transpose()
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let after = PageToken::decode(&input.page_token)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let page_size = pagination::page_size(input.page_size);
+
+        // One extra row tells whether another page follows.
+        let filter = TransactionFilter {
+            account_id: account.id,
+            from,
+            to,
+            transaction_type,
+            status,
+            min_amount,
+            max_amount,
+            after,
+            limit: page_size + 1,
+        };
+
+        let mut transactions = Transaction::list(self.db_pool.as_ref(), &filter)
+            .await
+            .map_err(|err| {
+                error!("Error while listing transactions: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        let next_page_token = if transactions.len() as i64 > page_size {
+            transactions.truncate(page_size as usize);
+
+            transactions
+                .last()
+                .map(|last| {
+                    PageToken {
+                        created_at: last.created_at.clone(),
+                        id: last.id.clone(),
+                    }
+                    .encode()
+                })
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        let response = proto::ListTransactionsResponse {
+            transactions: transactions.into_iter().map(Into::into).collect(),
+            next_page_token,
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_bank_account(
+        &self,
+        request: Request<proto::GetBankAccountRequest>,
+    ) -> Result<Response<proto::GetBankAccountResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a get bank account request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let account_id = Uuid::try_parse(&input.account_id)
+            .map_err(|_err| Status::not_found("Bank account not found".to_owned()))?;
+
+        let account = bank_account::BankAccount::find(self.db_pool.as_ref(), account_id)
+            .await
+            .map_err(|err| {
+                error!("Error finding bank account: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?
+            .ok_or_else(|| Status::not_found("Bank account not found".to_owned()))?;
+
+        caller.ensure_owns(&account.user_id)?;
+
+        let response = proto::GetBankAccountResponse {
+            account: Some(account.into()),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn list_bank_accounts(
+        &self,
+        request: Request<proto::ListBankAccountsRequest>,
+    ) -> Result<Response<proto::ListBankAccountsResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a list bank accounts request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let accounts = bank_account::BankAccount::list_by_user(self.db_pool.as_ref(), user_id)
+            .await
+            .map_err(|err| {
+                error!("Error while listing bank accounts: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        let response = proto::ListBankAccountsResponse {
+            accounts: accounts.into_iter().map(Into::into).collect(),
         };
 
         Ok(Response::new(response))
     }
+
+    async fn watch_account(
+        &self,
+        request: Request<proto::WatchAccountRequest>,
+    ) -> Result<Response<Self::WatchAccountStream>, Status> {
+        self.incremet_counter().await;
+        info!("Received a watch account request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        // Subscribe before reading the balance so nothing committed in
+        // between is missed.
+        let mut events = self.events.subscribe();
+
+        let account_id = Uuid::try_parse(&input.account_id)
+            .map_err(|_err| Status::not_found("Bank account not found".to_owned()))?;
+
+        let account = bank_account::BankAccount::find(self.db_pool.as_ref(), account_id)
+            .await
+            .map_err(|err| {
+                error!("Error finding bank account: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?
+            .ok_or_else(|| Status::not_found("Bank account not found".to_owned()))?;
+
+        caller.ensure_owns(&account.user_id)?;
+
+        let (sender, receiver) = mpsc::channel(WATCH_BUFFER_SIZE);
+        let db_pool = self.db_pool.clone();
+        let account_id = account.id.to_string();
+
+        tokio::spawn(async move {
+            if sender.send(Ok(balance_snapshot(&account))).await.is_err() {
+                return;
+            }
+
+            loop {
+                let received = tokio::select! {
+                    received = events.recv() => received,
+                    _ = sender.closed() => break,
+                };
+
+                let event = match received {
+                    Ok(event) if event.account_id() == account_id => event.into(),
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        // The client is too slow and missed events; resend
+                        // the current balance so it can resynchronize.
+                        info!(
+                            "Watcher of account {} skipped {} events, resending balance.",
+                            account_id, skipped
+                        );
+
+                        match bank_account::BankAccount::find(db_pool.as_ref(), account.id).await {
+                            Ok(Some(account)) => balance_snapshot(&account),
+                            Ok(None) => break,
+                            Err(err) => {
+                                error!("Error finding bank account: {:?}", err);
+                                let _ = sender
+                                    .send(Err(Status::internal("Internal server error")))
+                                    .await;
+                                break;
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                if sender.send(Ok(event)).await.is_err() {
+                    break;
+                }
+            }
+
+            info!("Watcher of account {} disconnected.", account_id);
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn import_transactions(
+        &self,
+        request: Request<tonic::Streaming<proto::ExecuteTransactionRequest>>,
+    ) -> Result<Response<proto::ImportSummary>, Status> {
+        self.incremet_counter().await;
+        info!("Received an import transactions request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+
+        let all_or_nothing = match request.metadata().get(IMPORT_MODE_HEADER) {
+            None => false,
+            Some(value) => match value.to_str() {
+                Ok("best-effort") => false,
+                Ok("all-or-nothing") => true,
+                _ => {
+                    return Err(Status::invalid_argument(format!(
+                        "The {} header must be best-effort or all-or-nothing",
+                        IMPORT_MODE_HEADER
+                    )))
+                }
+            },
+        };
+
+        let mut stream = request.into_inner();
+        let mut lines = Vec::new();
+
+        while let Some(input) = stream.message().await? {
+            if lines.len() == MAX_IMPORT_LINES {
+                return Err(Status::invalid_argument(format!(
+                    "An import is limited to {} lines",
+                    MAX_IMPORT_LINES
+                )));
+            }
+
+            lines.push(input);
+        }
+
+        let mut errors = Vec::new();
+        let mut valid = Vec::with_capacity(lines.len());
+
+        for (index, input) in lines.into_iter().enumerate() {
+            let line = index + 1;
+
+            let account_id = match Uuid::try_parse(&input.account_id) {
+                Ok(account_id) => account_id,
+                Err(_err) => {
+                    errors.push(import_error(line, "Bank account not found"));
+                    continue;
+                }
+            };
+
+            if input.pending {
+                errors.push(import_error(line, "Imported transactions can't be pending"));
+                continue;
+            }
+
+            match validate_transaction_input(&input) {
+                Ok(transaction_input) => {
+                    valid.push((line, account_id, transaction_input, input.description))
+                }
+                Err(message) => errors.push(import_error(line, message)),
+            }
+        }
+
+        let received = (errors.len() + valid.len()) as i32;
+
+        let mut txn = self.db_pool.as_ref().begin().await.map_err(|err| {
+            error!("Error while starting DB transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        // Same lock order as `TransferFunds`, so imports and transfers
+        // touching the same accounts can't deadlock.
+        let mut lock_order: Vec<Uuid> = valid.iter().map(|(_, id, ..)| *id).collect();
+        lock_order.sort();
+        lock_order.dedup();
+
+        let mut accounts = HashMap::new();
+
+        for account_id in lock_order {
+            let account = bank_account::BankAccount::find_for_update(&mut txn, account_id)
+                .await
+                .map_err(|err| {
+                    error!("Error finding bank account: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?;
+
+            if let Some(account) = account.filter(|account| account.user_id == caller.user_id) {
+                accounts.insert(account_id, account);
+            }
+        }
+
+        let mut category_ids: Vec<Uuid> = valid
+            .iter()
+            .filter_map(|(_, _, transaction_input, _)| transaction_input.category_id)
+            .collect();
+        category_ids.sort();
+        category_ids.dedup();
+
+        let visible_categories = Category::visible_ids(&mut *txn, caller.user_id, &category_ids)
+            .await
+            .map_err(|err| {
+                error!("Error finding categories: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        let rules = RuleSet::load(&mut *txn, caller.user_id).await?;
+
+        let mut imported = Vec::with_capacity(valid.len());
+
+        for (line, account_id, transaction_input, description) in valid {
+            // Accounts of other users are reported like missing ones, to not
+            // reveal which ids exist.
+            let Some(account) = accounts.get_mut(&account_id) else {
+                errors.push(import_error(line, "Bank account not found"));
+                continue;
+            };
+
+            if let Err(err) = transaction_input.ensure_currency(account) {
+                errors.push(import_error(line, err.to_string()));
+                continue;
+            }
+
+            if let Some(category_id) = transaction_input.category_id {
+                if !visible_categories.contains(&category_id) {
+                    errors.push(import_error(line, CategoryError::NotFound.to_string()));
+                    continue;
+                }
+            }
+
+            let mut transaction =
+                transaction_input.into_transaction(account_id.to_string(), description);
+            rules.apply(&mut transaction, false);
+
+            if let Err(err) = account.update_balance(&transaction) {
+                errors.push(import_error(line, err.to_string()));
+                continue;
+            }
+
+            imported.push((transaction, account.balance, account.available_balance));
+        }
+
+        errors.sort_by_key(|error| error.line);
+
+        if all_or_nothing && !errors.is_empty() {
+            txn.rollback().await.map_err(|err| {
+                error!("Failed to roll back the import: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+            return Ok(Response::new(proto::ImportSummary {
+                received,
+                imported: 0,
+                errors,
+                committed: false,
+            }));
+        }
+
+        let journal_entries: Vec<JournalEntry> = imported
+            .iter()
+            .map(|(transaction, ..)| JournalEntry::for_transaction(transaction))
+            .collect();
+
+        JournalEntry::insert_many(&mut txn, &journal_entries)
+            .await
+            .map_err(|err| {
+                error!(
+                    "Error while posting imported transactions to the ledger: {:?}",
+                    err
+                );
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        let transactions: Vec<Transaction> = imported
+            .iter()
+            .map(|(transaction, ..)| transaction.clone())
+            .collect();
+
+        Transaction::insert_many(&mut txn, &transactions)
+            .await
+            .map_err(|err| {
+                error!("Error while inserting imported transactions: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        tag::attach(&mut txn, caller.user_id, &transactions)
+            .await
+            .map_err(|err| {
+                error!("Error while tagging imported transactions: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        // One balance write per account, with the net of all its lines.
+        for account in accounts.values() {
+            account.save_balance(&mut txn).await.map_err(|err| {
+                error!("Error while updating account balance: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+            ledger::verify_balance(&mut txn, account)
+                .await
+                .map_err(|err| {
+                    error!("Error while verifying account balance: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?;
+        }
+
+        txn.commit().await.map_err(|err| {
+            error!("Failed to commit the import: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        let summary = proto::ImportSummary {
+            received,
+            imported: imported.len() as i32,
+            errors,
+            committed: true,
+        };
+
+        for (transaction, balance, available_balance) in imported {
+            self.events.publish(AccountEvent::TransactionPosted {
+                transaction,
+                balance,
+                available_balance,
+            });
+        }
+
+        Ok(Response::new(summary))
+    }
+
+    async fn import_statement(
+        &self,
+        request: Request<proto::ImportStatementRequest>,
+    ) -> Result<Response<proto::ImportStatementResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received an import statement request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let format = proto::StatementFormat::try_from(input.format)
+            .map_err(|_err| Status::invalid_argument("Invalid statement format".to_owned()))?;
+
+        let parse = match format {
+            proto::StatementFormat::Csv => statement::parse_csv,
+            proto::StatementFormat::Ofx => statement::parse_ofx,
+            proto::StatementFormat::JsonLines => {
+                return Err(Status::invalid_argument(
+                    "JSON Lines statements can't be imported".to_owned(),
+                ))
+            }
+        };
+
+        let mut txn = self.db_pool.as_ref().begin().await.map_err(|err| {
+            error!("Error while starting DB transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        let account_id = Uuid::try_parse(&input.account_id)
+            .map_err(|_err| Status::invalid_argument("Bank account not found".to_owned()))?;
+
+        // Locking the account also serializes imports of the same statement,
+        // so both can't miss each other's lines when looking for duplicates.
+        let mut account = bank_account::BankAccount::find_for_update(&mut txn, account_id)
+            .await
+            .map_err(|err| {
+                error!("Error finding bank account: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?
+            .ok_or_else(|| Status::invalid_argument("Bank account not found".to_owned()))?;
+
+        caller.ensure_owns(&account.user_id)?;
+
+        // Amounts are read with the decimal places of the account currency.
+        let parsed = parse(&input.content, &account.currency)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let recorded = match statement::covered_range(&parsed.lines) {
+            Some((from, to)) => {
+                let filter = TransactionFilter {
+                    account_id: account.id,
+                    from: Some(from),
+                    to: Some(to),
+                    transaction_type: None,
+                    status: None,
+                    min_amount: None,
+                    max_amount: None,
+                    after: None,
+                    limit: i64::MAX,
+                };
+
+                Transaction::list(&mut *txn, &filter).await.map_err(|err| {
+                    error!("Error while listing transactions: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?
+            }
+            None => Vec::new(),
+        };
+
+        let external_ids: Vec<String> = parsed
+            .lines
+            .iter()
+            .filter_map(|line| line.external_id.clone())
+            .collect();
+
+        let imported_ids = Transaction::find_external_ids(&mut *txn, account.id, &external_ids)
+            .await
+            .map_err(|err| {
+                error!("Error while looking up imported statement lines: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        let duplicates = statement::find_duplicates(&parsed.lines, &recorded, &imported_ids);
+
+        let mut errors: Vec<proto::ImportError> = parsed
+            .errors
+            .into_iter()
+            .map(|error| import_error(error.line, error.message))
+            .collect();
+
+        // Statements often list the newest lines first, so they are applied
+        // by date for the balance checks to follow the actual history.
+        let mut order: Vec<usize> = (0..parsed.lines.len())
+            .filter(|&index| !duplicates[index])
+            .collect();
+        order.sort_by_key(|&index| (parsed.lines[index].date, parsed.lines[index].line));
+
+        let rules = RuleSet::load(&mut *txn, account.user_id).await?;
+
+        let mut transaction_ids = vec![None; parsed.lines.len()];
+        let mut imported = Vec::with_capacity(order.len());
+
+        for index in order {
+            let line = &parsed.lines[index];
+            let mut transaction = line.to_transaction(account.id.to_string());
+            rules.apply(&mut transaction, false);
+
+            if let Err(err) = account.update_balance(&transaction) {
+                errors.push(import_error(line.line, err.to_string()));
+                continue;
+            }
+
+            if !input.preview {
+                transaction_ids[index] = Some(transaction.id.clone());
+            }
+
+            imported.push((transaction, account.balance, account.available_balance));
+        }
+
+        errors.sort_by_key(|error| error.line);
+
+        let entries = parsed
+            .lines
+            .into_iter()
+            .zip(duplicates)
+            .zip(transaction_ids)
+            .map(
+                |((line, duplicate), transaction_id)| proto::StatementEntry {
+                    line: line.line as i32,
+                    date: line.date.to_string(),
+                    transaction_type: line.transaction_type.to_proto(),
+                    amount_minor: line.amount.to_wire(),
+                    description: line.description,
+                    external_id: line.external_id,
+                    duplicate,
+                    transaction_id,
+                },
+            )
+            .collect::<Vec<_>>();
+
+        let mut response = proto::ImportStatementResponse {
+            duplicates: entries.iter().filter(|entry| entry.duplicate).count() as i32,
+            entries,
+            errors,
+            imported: imported.len() as i32,
+            committed: false,
+        };
+
+        if input.preview {
+            return Ok(Response::new(response));
+        }
+
+        let journal_entries: Vec<JournalEntry> = imported
+            .iter()
+            .map(|(transaction, ..)| JournalEntry::for_transaction(transaction))
+            .collect();
+
+        JournalEntry::insert_many(&mut txn, &journal_entries)
+            .await
+            .map_err(|err| {
+                error!("Error while posting the statement to the ledger: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        let transactions: Vec<Transaction> = imported
+            .iter()
+            .map(|(transaction, ..)| transaction.clone())
+            .collect();
+
+        Transaction::insert_many(&mut txn, &transactions)
+            .await
+            .map_err(|err| {
+                error!("Error while inserting statement transactions: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        tag::attach(&mut txn, account.user_id, &transactions)
+            .await
+            .map_err(|err| {
+                error!("Error while tagging statement transactions: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        account.save_balance(&mut txn).await.map_err(|err| {
+            error!("Error while updating account balance: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        ledger::verify_balance(&mut txn, &account)
+            .await
+            .map_err(|err| {
+                error!("Error while verifying account balance: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        txn.commit().await.map_err(|err| {
+            error!("Failed to commit the statement import: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        response.committed = true;
+
+        for (transaction, balance, available_balance) in imported {
+            self.events.publish(AccountEvent::TransactionPosted {
+                transaction,
+                balance,
+                available_balance,
+            });
+        }
+
+        Ok(Response::new(response))
+    }
+
+    async fn export_statement(
+        &self,
+        request: Request<proto::ExportStatementRequest>,
+    ) -> Result<Response<Self::ExportStatementStream>, Status> {
+        self.incremet_counter().await;
+        info!("Received an export statement request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let format = match proto::StatementFormat::try_from(input.format) {
+            Ok(proto::StatementFormat::Csv) => ExportFormat::Csv,
+            Ok(proto::StatementFormat::Ofx) => ExportFormat::Ofx,
+            Ok(proto::StatementFormat::JsonLines) => ExportFormat::JsonLines,
+            Err(_err) => {
+                return Err(Status::invalid_argument(
+                    "Invalid statement format".to_owned(),
+                ))
+            }
+        };
+
+        let account_id = Uuid::try_parse(&input.account_id)
+            .map_err(|_err| Status::invalid_argument("Bank account not found".to_owned()))?;
+
+        let account = bank_account::BankAccount::find(self.db_pool.as_ref(), account_id)
+            .await
+            .map_err(|err| {
+                error!("Error finding bank account: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?
+            .ok_or_else(|| Status::invalid_argument("Bank account not found".to_owned()))?;
+
+        caller.ensure_owns(&account.user_id)?;
+
+        let from = input
+            .from
+            .as_deref()
+            .map(pagination::parse_timestamp)
+            .transpose()
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let to = match input.to.as_deref() {
+            Some(to) => pagination::parse_timestamp(to)
+                .map_err(|err| Status::invalid_argument(err.to_string()))?,
+            None => Utc::now()
+                .naive_utc()
+                .format(pagination::TIMESTAMP_FORMAT)
+                .to_string(),
+        };
+
+        let (sender, receiver) = mpsc::channel(EXPORT_BUFFER_SIZE);
+        let db_pool = self.db_pool.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = send_statement(&db_pool, &sender, account, format, from, to).await {
+                error!("Error while exporting statement: {:?}", err);
+                let _ = sender
+                    .send(Err(Status::internal("Internal server error".to_owned())))
+                    .await;
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn get_summary(
+        &self,
+        request: Request<proto::GetSummaryRequest>,
+    ) -> Result<Response<proto::GetSummaryResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a get summary request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let period = Period::from_proto(&input.period).map_err(Status::invalid_argument)?;
+
+        let from = input
+            .from
+            .as_deref()
+            .map(pagination::parse_timestamp)
+            .transpose()
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let to = input
+            .to
+            .as_deref()
+            .map(pagination::parse_timestamp)
+            .transpose()
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // Both queries read the same snapshot, so the ending balances add up
+        // with the opening ones.
+        let mut txn = self.db_pool.as_ref().begin().await.map_err(|err| {
+            error!("Error while starting DB transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .execute(&mut *txn)
+            .await
+            .map_err(|err| {
+                error!("Error while starting DB transaction: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        let opening_balances = match from.as_deref() {
+            Some(from) => report::balances_before(&mut *txn, user_id, from)
+                .await
+                .map_err(|err| {
+                    error!("Error while computing opening balances: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?,
+            None => Vec::new(),
+        };
+
+        let activity =
+            report::account_activity(&mut *txn, user_id, period, from.as_deref(), to.as_deref())
+                .await
+                .map_err(|err| {
+                    error!("Error while summarizing transactions: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?;
+
+        let category_activity =
+            report::category_activity(&mut *txn, user_id, period, from.as_deref(), to.as_deref())
+                .await
+                .map_err(|err| {
+                    error!("Error while summarizing categories: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?;
+
+        txn.commit().await.map_err(|err| {
+            error!("Failed to commit summary: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        let buckets =
+            summary_buckets(opening_balances, activity, category_activity).map_err(|err| {
+                error!("Error while summarizing transactions: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        Ok(Response::new(proto::GetSummaryResponse { buckets }))
+    }
+
+    async fn create_category(
+        &self,
+        request: Request<proto::CreateCategoryRequest>,
+    ) -> Result<Response<proto::CreateCategoryResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a create category request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let parent_id = input
+            .parent_id
+            .as_deref()
+            .map(Uuid::try_parse)
+            .transpose()
+            .map_err(|_err| CategoryError::NotFound)?;
+
+        if let Some(parent_id) = parent_id {
+            Category::find_visible(self.db_pool.as_ref(), user_id, parent_id)
+                .await
+                .map_err(CategoryError::from)?
+                .ok_or(CategoryError::NotFound)?;
+        }
+
+        let category = Category::new(user_id, parent_id, &input.name)?;
+
+        category.insert(self.db_pool.as_ref()).await?;
+
+        Ok(Response::new(proto::CreateCategoryResponse {
+            category: Some(category.into()),
+        }))
+    }
+
+    async fn list_categories(
+        &self,
+        request: Request<proto::ListCategoriesRequest>,
+    ) -> Result<Response<proto::ListCategoriesResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a list categories request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let categories = Category::list_visible(self.db_pool.as_ref(), user_id)
+            .await
+            .map_err(CategoryError::from)?;
+
+        Ok(Response::new(proto::ListCategoriesResponse {
+            categories: categories.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn update_category(
+        &self,
+        request: Request<proto::UpdateCategoryRequest>,
+    ) -> Result<Response<proto::UpdateCategoryResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received an update category request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let category_id =
+            Uuid::try_parse(&input.category_id).map_err(|_err| CategoryError::NotFound)?;
+
+        let mut category =
+            Category::find_visible(self.db_pool.as_ref(), caller.user_id, category_id)
+                .await
+                .map_err(CategoryError::from)?
+                .ok_or(CategoryError::NotFound)?;
+
+        if category.is_system() {
+            return Err(CategoryError::ReadOnly.into());
+        }
+
+        if let Some(name) = &input.name {
+            category.rename(name)?;
+        }
+
+        match input.parent_id.as_deref() {
+            None => {}
+            Some("") => category.parent_id = None,
+            Some(parent_id) => {
+                let parent_id =
+                    Uuid::try_parse(parent_id).map_err(|_err| CategoryError::NotFound)?;
+
+                Category::find_visible(self.db_pool.as_ref(), caller.user_id, parent_id)
+                    .await
+                    .map_err(CategoryError::from)?
+                    .ok_or(CategoryError::NotFound)?;
+
+                let is_cycle = Category::is_within(self.db_pool.as_ref(), parent_id, category.id)
+                    .await
+                    .map_err(CategoryError::from)?;
+
+                if is_cycle {
+                    return Err(CategoryError::Cycle.into());
+                }
+
+                category.parent_id = Some(parent_id);
+            }
+        }
+
+        category.update(self.db_pool.as_ref()).await?;
+
+        Ok(Response::new(proto::UpdateCategoryResponse {
+            category: Some(category.into()),
+        }))
+    }
+
+    async fn delete_category(
+        &self,
+        request: Request<proto::DeleteCategoryRequest>,
+    ) -> Result<Response<proto::DeleteCategoryResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a delete category request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let category_id =
+            Uuid::try_parse(&input.category_id).map_err(|_err| CategoryError::NotFound)?;
+
+        let category = Category::find_visible(self.db_pool.as_ref(), caller.user_id, category_id)
+            .await
+            .map_err(CategoryError::from)?
+            .ok_or(CategoryError::NotFound)?;
+
+        if category.is_system() {
+            return Err(CategoryError::ReadOnly.into());
+        }
+
+        let mut txn = self
+            .db_pool
+            .as_ref()
+            .begin()
+            .await
+            .map_err(CategoryError::from)?;
+
+        category.delete(&mut txn).await?;
+
+        txn.commit().await.map_err(CategoryError::from)?;
+
+        Ok(Response::new(proto::DeleteCategoryResponse {}))
+    }
+
+    async fn create_rule(
+        &self,
+        request: Request<proto::CreateRuleRequest>,
+    ) -> Result<Response<proto::CreateRuleResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a create rule request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let definition = input.rule.unwrap_or_default();
+        let rule = rule_from_definition(user_id, &definition).map_err(Status::invalid_argument)?;
+
+        if let Some(account_id) = rule.account_id {
+            bank_account::BankAccount::find(self.db_pool.as_ref(), account_id)
+                .await
+                .map_err(|err| {
+                    error!("Error finding bank account: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?
+                .filter(|account| account.user_id == user_id)
+                .ok_or_else(|| Status::invalid_argument("Bank account not found".to_owned()))?;
+        }
+
+        if let Some(category_id) = rule.category_id {
+            Category::find_visible(self.db_pool.as_ref(), user_id, category_id)
+                .await
+                .map_err(CategoryError::from)?
+                .ok_or_else(|| Status::invalid_argument(CategoryError::NotFound.to_string()))?;
+        }
+
+        rule.insert(self.db_pool.as_ref())
+            .await
+            .map_err(RuleError::from)?;
+
+        Ok(Response::new(proto::CreateRuleResponse {
+            rule: Some(rule.into()),
+        }))
+    }
+
+    async fn list_rules(
+        &self,
+        request: Request<proto::ListRulesRequest>,
+    ) -> Result<Response<proto::ListRulesResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a list rules request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let rules = Rule::list_by_user(self.db_pool.as_ref(), user_id)
+            .await
+            .map_err(RuleError::from)?;
+
+        Ok(Response::new(proto::ListRulesResponse {
+            rules: rules.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn delete_rule(
+        &self,
+        request: Request<proto::DeleteRuleRequest>,
+    ) -> Result<Response<proto::DeleteRuleResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a delete rule request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let rule_id = Uuid::try_parse(&input.rule_id).map_err(|_err| RuleError::NotFound)?;
+
+        let rule = Rule::find_owned(self.db_pool.as_ref(), caller.user_id, rule_id)
+            .await
+            .map_err(RuleError::from)?
+            .ok_or(RuleError::NotFound)?;
+
+        rule.delete(self.db_pool.as_ref())
+            .await
+            .map_err(RuleError::from)?;
+
+        Ok(Response::new(proto::DeleteRuleResponse {}))
+    }
+
+    async fn test_rule(
+        &self,
+        request: Request<proto::TestRuleRequest>,
+    ) -> Result<Response<proto::TestRuleResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a test rule request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let limit = match input.limit {
+            0 => DEFAULT_RULE_TEST_MATCHES,
+            limit if (1..=MAX_RULE_TEST_MATCHES).contains(&limit) => limit,
+            _ => {
+                return Err(Status::invalid_argument(format!(
+                    "The limit must be between 1 and {}",
+                    MAX_RULE_TEST_MATCHES
+                )))
+            }
+        };
+
+        let definition = input.rule.unwrap_or_default();
+        let rule = rule_from_definition(user_id, &definition).map_err(Status::invalid_argument)?;
+        let rules = RuleSet::new(vec![rule])?;
+
+        let transactions =
+            Transaction::latest_for_user(self.db_pool.as_ref(), user_id, RULE_TEST_SCAN_SIZE)
+                .await
+                .map_err(|err| {
+                    error!("Error while listing transactions: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?;
+
+        let scanned = transactions.len() as i32;
+
+        let matches = transactions
+            .into_iter()
+            .filter(|transaction| rules.matches(transaction))
+            .take(limit as usize)
+            .map(Into::into)
+            .collect();
+
+        Ok(Response::new(proto::TestRuleResponse { matches, scanned }))
+    }
+
+    async fn apply_rules(
+        &self,
+        request: Request<proto::ApplyRulesRequest>,
+    ) -> Result<Response<proto::ApplyRulesResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received an apply rules request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let mut txn = self.db_pool.as_ref().begin().await.map_err(|err| {
+            error!("Error while starting DB transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        let rules = RuleSet::load(&mut *txn, user_id).await?;
+
+        let mut scanned = 0;
+        let mut updated = 0;
+        let mut after = None;
+
+        while !rules.is_empty() {
+            let page = Transaction::list_for_user(
+                &mut *txn,
+                user_id,
+                after.as_ref(),
+                APPLY_RULES_PAGE_SIZE,
+            )
+            .await
+            .map_err(|err| {
+                error!("Error while listing transactions: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+            let Some(last) = page.last() else {
+                break;
+            };
+
+            after = Some(PageToken {
+                created_at: last.created_at.clone(),
+                id: last.id.clone(),
+            });
+            scanned += page.len() as i32;
+
+            let changed: Vec<Transaction> = page
+                .into_iter()
+                .filter(|transaction| {
+                    !input.only_uncategorized || transaction.category_id.is_none()
+                })
+                .filter_map(|mut transaction| {
+                    rules
+                        .apply(&mut transaction, !input.only_uncategorized)
+                        .then_some(transaction)
+                })
+                .collect();
+
+            Transaction::update_categories(&mut txn, &changed, "Categorization rules applied")
+                .await
+                .map_err(|err| {
+                    error!("Error while categorizing transactions: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?;
+
+            tag::attach(&mut txn, user_id, &changed)
+                .await
+                .map_err(|err| {
+                    error!("Error while tagging transactions: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?;
+
+            updated += changed.len() as i32;
+        }
+
+        txn.commit().await.map_err(|err| {
+            error!("Failed to commit the rules: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        Ok(Response::new(proto::ApplyRulesResponse {
+            scanned,
+            updated,
+        }))
+    }
+
+    async fn set_budget(
+        &self,
+        request: Request<proto::SetBudgetRequest>,
+    ) -> Result<Response<proto::SetBudgetResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a set budget request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let limit = Money::from_wire(input.limit_minor)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        let category_id = Uuid::try_parse(&input.category_id)
+            .map_err(|_err| Status::invalid_argument(CategoryError::NotFound.to_string()))?;
+
+        Category::find_visible(self.db_pool.as_ref(), user_id, category_id)
+            .await
+            .map_err(CategoryError::from)?
+            .ok_or_else(|| Status::invalid_argument(CategoryError::NotFound.to_string()))?;
+
+        let currency = Currency::normalize_code(&input.currency, DEFAULT_CURRENCY)?;
+
+        Currency::find(self.db_pool.as_ref(), &currency)
+            .await
+            .map_err(CurrencyError::from)?
+            .ok_or(CurrencyError::Unsupported)?;
+
+        if limit.is_zero() {
+            Budget::delete_for_category(self.db_pool.as_ref(), user_id, category_id, &currency)
+                .await
+                .map_err(BudgetError::from)?;
+
+            return Ok(Response::new(proto::SetBudgetResponse { budget: None }));
+        }
+
+        let budget = Budget::new(user_id, category_id, currency, limit)
+            .upsert(self.db_pool.as_ref())
+            .await
+            .map_err(BudgetError::from)?;
+
+        Ok(Response::new(proto::SetBudgetResponse {
+            budget: Some(budget.into()),
+        }))
+    }
+
+    async fn list_budgets(
+        &self,
+        request: Request<proto::ListBudgetsRequest>,
+    ) -> Result<Response<proto::ListBudgetsResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a list budgets request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let budgets = Budget::list_by_user(self.db_pool.as_ref(), user_id)
+            .await
+            .map_err(BudgetError::from)?;
+
+        Ok(Response::new(proto::ListBudgetsResponse {
+            budgets: budgets.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn get_budget_status(
+        &self,
+        request: Request<proto::GetBudgetStatusRequest>,
+    ) -> Result<Response<proto::GetBudgetStatusResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a get budget status request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let month = match input.month.as_deref() {
+            Some(month) => Month::parse(month)?,
+            None => Month::current(),
+        };
+
+        let statuses = budget::statuses(self.db_pool.as_ref(), user_id, month)
+            .await
+            .map_err(BudgetError::from)?;
+
+        Ok(Response::new(proto::GetBudgetStatusResponse {
+            month: month.to_string(),
+            budgets: statuses.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn schedule_transaction(
+        &self,
+        request: Request<proto::ScheduleTransactionRequest>,
+    ) -> Result<Response<proto::ScheduleTransactionResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a schedule transaction request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let transaction_request = input.transaction.unwrap_or_default();
+        let transaction_input =
+            validate_transaction_input(&transaction_request).map_err(Status::invalid_argument)?;
+
+        if transaction_input.pending {
+            return Err(Status::invalid_argument(
+                "Scheduled transactions can't be pending".to_owned(),
+            ));
+        }
+
+        if transaction_input.occurred_at.is_some() {
+            return Err(Status::invalid_argument(
+                "Only imported transactions can set occurred_at".to_owned(),
+            ));
+        }
+
+        let starts_at = match input.starts_at.as_deref() {
+            Some(starts_at) => schedule::parse_timestamp(starts_at)?,
+            None => Utc::now().naive_utc(),
+        };
+
+        let recurrence = match input.recurrence {
+            None => Recurrence::once(),
+            Some(recurrence) => Recurrence {
+                frequency: Frequency::from_proto(&recurrence.frequency)?,
+                interval: if recurrence.interval == 0 {
+                    1
+                } else {
+                    recurrence.interval
+                },
+                day_of_month: recurrence.day_of_month,
+                ends_at: recurrence
+                    .ends_at
+                    .as_deref()
+                    .map(schedule::parse_timestamp)
+                    .transpose()?,
+                max_occurrences: recurrence.count,
+            },
+        };
+
+        let account_id = Uuid::try_parse(&transaction_request.account_id)
+            .map_err(|_err| Status::invalid_argument("Bank account not found".to_owned()))?;
+
+        let account = bank_account::BankAccount::find(self.db_pool.as_ref(), account_id)
+            .await
+            .map_err(|err| {
+                error!("Error finding bank account: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?
+            .ok_or_else(|| Status::invalid_argument("Bank account not found".to_owned()))?;
+
+        caller.ensure_owns(&account.user_id)?;
+
+        transaction_input.ensure_currency(&account)?;
+
+        if let Some(category_id) = transaction_input.category_id {
+            Category::find_visible(self.db_pool.as_ref(), caller.user_id, category_id)
+                .await
+                .map_err(CategoryError::from)?
+                .ok_or_else(|| Status::invalid_argument(CategoryError::NotFound.to_string()))?;
+        }
+
+        let template = transaction_input
+            .into_transaction(account.id.to_string(), transaction_request.description);
+        let schedule = ScheduledTransaction::new(caller.user_id, &template, starts_at, recurrence)?;
+
+        schedule
+            .insert(self.db_pool.as_ref())
+            .await
+            .map_err(ScheduleError::from)?;
+
+        Ok(Response::new(proto::ScheduleTransactionResponse {
+            scheduled_transaction: Some(schedule.into()),
+        }))
+    }
+
+    async fn list_scheduled_transactions(
+        &self,
+        request: Request<proto::ListScheduledTransactionsRequest>,
+    ) -> Result<Response<proto::ListScheduledTransactionsResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a list scheduled transactions request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let user_id = Uuid::try_parse(&input.user_id)
+            .map_err(|_err| Status::invalid_argument("User not found".to_owned()))?;
+
+        caller.ensure_owns(&user_id)?;
+
+        let schedules = ScheduledTransaction::list_by_user(self.db_pool.as_ref(), user_id)
+            .await
+            .map_err(ScheduleError::from)?;
+
+        Ok(Response::new(proto::ListScheduledTransactionsResponse {
+            scheduled_transactions: schedules.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn cancel_scheduled_transaction(
+        &self,
+        request: Request<proto::CancelScheduledTransactionRequest>,
+    ) -> Result<Response<proto::CancelScheduledTransactionResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a cancel scheduled transaction request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let schedule_id = Uuid::try_parse(&input.scheduled_transaction_id)
+            .map_err(|_err| ScheduleError::NotFound)?;
+
+        let mut schedule =
+            ScheduledTransaction::find_owned(self.db_pool.as_ref(), caller.user_id, schedule_id)
+                .await
+                .map_err(ScheduleError::from)?
+                .ok_or(ScheduleError::NotFound)?;
+
+        schedule.cancel(self.db_pool.as_ref()).await?;
+
+        Ok(Response::new(proto::CancelScheduledTransactionResponse {
+            scheduled_transaction: Some(schedule.into()),
+        }))
+    }
+
+    async fn reverse_transaction(
+        &self,
+        request: Request<proto::ReverseTransactionRequest>,
+    ) -> Result<Response<proto::ReverseTransactionResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a reverse transaction request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let transaction_id = Uuid::try_parse(&input.transaction_id)
+            .map_err(|_err| PostingError::TransactionNotFound)?;
+
+        if !is_valid_text(&input.reason) {
+            return Err(Status::invalid_argument(
+                "The reason must have between 1 and 255 characters".to_owned(),
+            ));
+        }
+
+        let mut txn = self.db_pool.as_ref().begin().await.map_err(|err| {
+            error!("Error while starting DB transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        let reversals = posting::reverse_transaction(
+            &mut txn,
+            caller.user_id,
+            transaction_id,
+            Some(input.reason),
+        )
+        .await?;
+
+        txn.commit().await.map_err(|err| {
+            error!("Failed to commit the reversal: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        let response = proto::ReverseTransactionResponse {
+            reversals: reversals
+                .iter()
+                .map(|posted| posted.transaction.clone().into())
+                .collect(),
+        };
+
+        for posted in reversals {
+            posted.publish(&self.events);
+        }
+
+        Ok(Response::new(response))
+    }
+
+    async fn amend_transaction(
+        &self,
+        request: Request<proto::AmendTransactionRequest>,
+    ) -> Result<Response<proto::AmendTransactionResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received an amend transaction request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let transaction_id = Uuid::try_parse(&input.transaction_id)
+            .map_err(|_err| PostingError::TransactionNotFound)?;
+
+        if input.description.is_none() && input.category_id.is_none() {
+            return Err(Status::invalid_argument(
+                "Give a description or a category to amend".to_owned(),
+            ));
+        }
+
+        if let Some(reason) = &input.reason {
+            if !is_valid_text(reason) {
+                return Err(Status::invalid_argument(
+                    "The reason must have between 1 and 255 characters".to_owned(),
+                ));
+            }
+        }
+
+        let mut txn = self.db_pool.as_ref().begin().await.map_err(|err| {
+            error!("Error while starting DB transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        let mut transaction = Transaction::find_for_update(&mut txn, transaction_id)
+            .await
+            .map_err(|err| {
+                error!("Error finding transaction: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?
+            .ok_or(PostingError::TransactionNotFound)?;
+
+        let account_id = Uuid::try_parse(&transaction.origin_account_id)
+            .map_err(|_err| PostingError::TransactionNotFound)?;
+
+        let account = bank_account::BankAccount::find(&mut *txn, account_id)
+            .await
+            .map_err(|err| {
+                error!("Error finding bank account: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?
+            .ok_or(PostingError::TransactionNotFound)?;
+
+        caller.ensure_owns(&account.user_id)?;
+
+        let description = match input.description {
+            None => transaction.description.clone(),
+            Some(description) if description.is_empty() => None,
+            Some(description) if is_valid_text(&description) => Some(description),
+            Some(_) => {
+                return Err(Status::invalid_argument(
+                    "The description must have at most 255 characters".to_owned(),
+                ))
+            }
+        };
+
+        let category_id = match input.category_id.as_deref() {
+            None => transaction.category_id.clone(),
+            Some("") => None,
+            Some(category_id) => {
+                let category_id =
+                    Uuid::try_parse(category_id).map_err(|_err| CategoryError::NotFound)?;
+
+                Category::find_visible(&mut *txn, caller.user_id, category_id)
+                    .await
+                    .map_err(CategoryError::from)?
+                    .ok_or(CategoryError::NotFound)?;
+
+                Some(category_id.to_string())
+            }
+        };
+
+        if description != transaction.description || category_id != transaction.category_id {
+            transaction
+                .amend(&mut txn, description, category_id, input.reason)
+                .await
+                .map_err(|err| {
+                    error!("Error while amending transaction: {:?}", err);
+                    Status::internal("Internal server error".to_owned())
+                })?;
+        }
+
+        txn.commit().await.map_err(|err| {
+            error!("Failed to commit the amendment: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        Ok(Response::new(proto::AmendTransactionResponse {
+            transaction: Some(transaction.into()),
+        }))
+    }
+
+    async fn get_transaction_history(
+        &self,
+        request: Request<proto::GetTransactionHistoryRequest>,
+    ) -> Result<Response<proto::GetTransactionHistoryResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a get transaction history request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let transaction_id = Uuid::try_parse(&input.transaction_id)
+            .map_err(|_err| PostingError::TransactionNotFound)?;
+
+        let transaction = Transaction::find(self.db_pool.as_ref(), transaction_id)
+            .await
+            .map_err(|err| {
+                error!("Error finding transaction: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?
+            .ok_or(PostingError::TransactionNotFound)?;
+
+        let account_id = Uuid::try_parse(&transaction.origin_account_id)
+            .map_err(|_err| PostingError::TransactionNotFound)?;
+
+        let account = bank_account::BankAccount::find(self.db_pool.as_ref(), account_id)
+            .await
+            .map_err(|err| {
+                error!("Error finding bank account: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?
+            .ok_or(PostingError::TransactionNotFound)?;
+
+        caller.ensure_owns(&account.user_id)?;
+
+        let versions = TransactionVersion::list(self.db_pool.as_ref(), transaction_id)
+            .await
+            .map_err(|err| {
+                error!("Error while listing transaction versions: {:?}", err);
+                Status::internal("Internal server error".to_owned())
+            })?;
+
+        Ok(Response::new(proto::GetTransactionHistoryResponse {
+            transaction: Some(transaction.into()),
+            versions: versions.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn post_transaction(
+        &self,
+        request: Request<proto::PostTransactionRequest>,
+    ) -> Result<Response<proto::PostTransactionResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a post transaction request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let transaction_id = Uuid::try_parse(&input.transaction_id)
+            .map_err(|_err| PostingError::TransactionNotFound)?;
+
+        let mut txn = self.db_pool.as_ref().begin().await.map_err(|err| {
+            error!("Error while starting DB transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        let posted = posting::resolve_pending(
+            &mut txn,
+            caller.user_id,
+            transaction_id,
+            TransactionStatus::POSTED,
+        )
+        .await?;
+
+        txn.commit().await.map_err(|err| {
+            error!("Failed to commit the posted transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        let response = proto::PostTransactionResponse {
+            transaction: Some(posted.transaction.clone().into()),
+        };
+
+        posted.publish(&self.events);
+
+        Ok(Response::new(response))
+    }
+
+    async fn void_transaction(
+        &self,
+        request: Request<proto::VoidTransactionRequest>,
+    ) -> Result<Response<proto::VoidTransactionResponse>, Status> {
+        self.incremet_counter().await;
+        info!("Received a void transaction request.");
+
+        let caller = AuthenticatedUser::from_request(&request)?;
+        let input = request.into_inner();
+
+        let transaction_id = Uuid::try_parse(&input.transaction_id)
+            .map_err(|_err| PostingError::TransactionNotFound)?;
+
+        let mut txn = self.db_pool.as_ref().begin().await.map_err(|err| {
+            error!("Error while starting DB transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        let voided = posting::resolve_pending(
+            &mut txn,
+            caller.user_id,
+            transaction_id,
+            TransactionStatus::VOIDED,
+        )
+        .await?;
+
+        txn.commit().await.map_err(|err| {
+            error!("Failed to commit the voided transaction: {:?}", err);
+            Status::internal("Internal server error".to_owned())
+        })?;
+
+        let response = proto::VoidTransactionResponse {
+            transaction: Some(voided.transaction.clone().into()),
+        };
+
+        voided.publish(&self.events);
+
+        Ok(Response::new(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(minor: i64) -> Money {
+        Money::from_wire(minor).unwrap()
+    }
+
+    fn activity(
+        bucket: &str,
+        account_id: &str,
+        currency: &str,
+        income: i64,
+        outcome: i64,
+    ) -> AccountActivity {
+        AccountActivity {
+            account_id: account_id.to_owned(),
+            currency: currency.to_owned(),
+            bucket: bucket.to_owned(),
+            income: money(income),
+            outcome: money(outcome),
+            external_income: money(income),
+            external_outcome: money(outcome),
+            net_change: money(income).checked_sub(money(outcome)).unwrap(),
+        }
+    }
+
+    #[test]
+    fn carries_balances_across_summary_buckets() {
+        let opening_balances = vec![
+            ("a".to_owned(), "USD".to_owned(), money(1000)),
+            ("b".to_owned(), "USD".to_owned(), money(500)),
+            ("c".to_owned(), "EUR".to_owned(), money(300)),
+        ];
+        let activity = vec![
+            activity("2024-01-01 00:00:00", "a", "USD", 200, 0),
+            activity("2024-02-01 00:00:00", "b", "USD", 0, 100),
+            activity("2024-03-01 00:00:00", "a", "USD", 0, 50),
+            activity("2024-03-01 00:00:00", "b", "USD", 25, 0),
+        ];
+        let category_activity = vec![CategoryActivity {
+            bucket: "2024-01-01 00:00:00".to_owned(),
+            currency: "USD".to_owned(),
+            category_id: None,
+            income: money(200),
+            outcome: money(0),
+            transaction_count: 1,
+        }];
+
+        let buckets = summary_buckets(opening_balances, activity, category_activity).unwrap();
+
+        let totals: Vec<_> = buckets
+            .iter()
+            .map(|bucket| {
+                (
+                    bucket.period_start.as_str(),
+                    bucket.income_minor,
+                    bucket.outcome_minor,
+                    bucket.net_change_minor,
+                    bucket.ending_balance_minor,
+                )
+            })
+            .collect();
+        assert_eq!(
+            totals,
+            [
+                ("2024-01-01 00:00:00", 200, 0, 200, 1700),
+                ("2024-02-01 00:00:00", 0, 100, -100, 1600),
+                ("2024-03-01 00:00:00", 25, 50, -25, 1575),
+            ]
+        );
+
+        // Only the accounts with activity are listed, but the idle one still
+        // counts towards the ending balance of the bucket.
+        let accounts: Vec<Vec<_>> = buckets
+            .iter()
+            .map(|bucket| {
+                bucket
+                    .accounts
+                    .iter()
+                    .map(|account| (account.account_id.as_str(), account.ending_balance_minor))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            accounts,
+            [
+                vec![("a", 1200)],
+                vec![("b", 400)],
+                vec![("a", 1150), ("b", 425)],
+            ]
+        );
+
+        assert_eq!(buckets[0].categories.len(), 1);
+        assert!(buckets[1].categories.is_empty());
+        assert!(buckets.iter().all(|bucket| bucket.currency == "USD"));
+    }
 }
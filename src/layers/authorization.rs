@@ -1,25 +1,83 @@
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
-use crate::tracing::info;
+use sqlx::postgres::PgPool;
+
+use crate::auth::{AuthenticatedUser, TokenError, TokenKeys};
+use crate::layers::policy::{access_for, Access};
+use crate::models::user::User;
+use crate::tracing::{error, info, warn};
 
 use tonic::body::BoxBody;
+use tonic::Status;
 use tower::{Layer, Service};
 
-#[derive(Debug, Clone, Default)]
-pub struct AuthorizationLayer {}
+#[derive(Clone)]
+pub struct AuthorizationLayer {
+    token_keys: Arc<TokenKeys>,
+    db_pool: Arc<PgPool>,
+}
+
+impl AuthorizationLayer {
+    pub fn new(token_keys: Arc<TokenKeys>, db_pool: Arc<PgPool>) -> Self {
+        AuthorizationLayer {
+            token_keys,
+            db_pool,
+        }
+    }
+}
 
 impl<S> Layer<S> for AuthorizationLayer {
     type Service = Authorization<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        Authorization { inner }
+        Authorization {
+            inner,
+            token_keys: self.token_keys.clone(),
+            db_pool: self.db_pool.clone(),
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Authorization<S> {
     pub inner: S,
+    token_keys: Arc<TokenKeys>,
+    db_pool: Arc<PgPool>,
+}
+
+impl<S> Authorization<S> {
+    fn authenticate<B>(&self, req: &hyper::Request<B>) -> Result<AuthenticatedUser, TokenError> {
+        let header = req
+            .headers()
+            .get("authorization")
+            .ok_or(TokenError::MissingHeader)?;
+
+        let token = header
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(TokenError::MalformedHeader)?;
+
+        self.token_keys.verify(token)
+    }
+}
+
+/// Rejects tokens issued before the user's token version was last bumped.
+async fn ensure_not_revoked(db_pool: &PgPool, user: &AuthenticatedUser) -> Result<(), TokenError> {
+    let current_version = User::current_token_version(db_pool, &user.user_id)
+        .await
+        .map_err(|err| {
+            error!("Error while checking the token version: {:?}", err);
+            TokenError::Lookup
+        })?;
+
+    if current_version != Some(user.token_version) {
+        return Err(TokenError::Revoked);
+    }
+
+    Ok(())
 }
 
 type BoxFuture<'a, T> = Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
@@ -40,13 +98,60 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, req: hyper::Request<BoxBody>) -> Self::Future {
-        let fut = self.inner.call(req);
-
+    fn call(&mut self, mut req: hyper::Request<BoxBody>) -> Self::Future {
         info!("Executing authorizationlayer verification");
 
+        let access = access_for(req.uri().path());
+
+        if *access == Access::Public {
+            let fut = self.inner.call(req);
+
+            return Box::pin(async move {
+                let res = fut.await?;
+                Ok(res)
+            });
+        }
+
+        let user = match self.authenticate(&req) {
+            Ok(user) => user,
+            Err(err) => {
+                warn!("Rejected request to {}: {}", req.uri().path(), err);
+                let status = Status::unauthenticated(err.to_string());
+                return Box::pin(async move { Ok(status.into_http()) });
+            }
+        };
+
+        if let Access::Roles(roles) = access {
+            if !roles.contains(&user.role) {
+                warn!(
+                    "Rejected request to {}: role {} is not allowed",
+                    req.uri().path(),
+                    user.role
+                );
+                let status = Status::permission_denied("Insufficient role for this method");
+                return Box::pin(async move { Ok(status.into_http()) });
+            }
+        }
+
+        // The ready service is moved into the future and replaced by a clone,
+        // as the call only happens after the async revocation check.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let db_pool = self.db_pool.clone();
+
         Box::pin(async move {
-            let res = fut.await?;
+            if let Err(err) = ensure_not_revoked(&db_pool, &user).await {
+                warn!("Rejected request to {}: {}", req.uri().path(), err);
+                let status = match err {
+                    TokenError::Lookup => Status::internal("Internal server error"),
+                    _ => Status::unauthenticated(err.to_string()),
+                };
+                return Ok(status.into_http());
+            }
+
+            req.extensions_mut().insert(user);
+
+            let res = inner.call(req).await?;
             Ok(res)
         })
     }
@@ -0,0 +1,37 @@
+use crate::models::user::Role;
+
+#[derive(Debug, PartialEq)]
+pub enum Access {
+    /// No access token required.
+    Public,
+    /// Any valid access token.
+    Authenticated,
+    /// A valid access token carrying one of the listed roles.
+    Roles(&'static [Role]),
+}
+
+/// Access policy per gRPC path. Entries ending in `/` apply to every method
+/// of a service; an exact method entry takes precedence over its service.
+/// Paths without an entry require an authenticated caller.
+const POLICIES: &[(&str, Access)] = &[
+    (
+        "/finance_control.FinanceControl/RegisterUser",
+        Access::Public,
+    ),
+    ("/finance_control.FinanceControl/Login", Access::Public),
+    ("/grpc.reflection.v1alpha.ServerReflection/", Access::Public),
+    ("/grpc.reflection.v1.ServerReflection/", Access::Public),
+    ("/finance_control.Admin/", Access::Roles(&[Role::ADMIN])),
+];
+
+pub fn access_for(path: &str) -> &'static Access {
+    if let Some((_, access)) = POLICIES.iter().find(|(method, _)| *method == path) {
+        return access;
+    }
+
+    POLICIES
+        .iter()
+        .find(|(service, _)| service.ends_with('/') && path.starts_with(service))
+        .map(|(_, access)| access)
+        .unwrap_or(&Access::Authenticated)
+}
@@ -7,14 +7,22 @@ use std::{net::SocketAddr, sync::Arc};
 use tokio::signal;
 use tonic::transport::Server;
 
+use auth::TokenKeys;
+use events::AccountEvents;
 use handlers::admin::AdminService;
 use handlers::finance_control::FinanceControlService;
 use layers::authorization::AuthorizationLayer;
 use tracing::{info, warn, Tracing};
 
+pub mod auth;
+pub mod events;
 pub mod handlers;
 pub mod layers;
 pub mod models;
+pub mod pagination;
+pub mod posting;
+pub mod scheduler;
+pub mod statement;
 pub mod tracing;
 
 mod proto {
@@ -32,6 +40,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Tracing::init();
 
+    let token_keys = Arc::new(TokenKeys::from_env().unwrap_or_else(|err| panic!("{}", err)));
+
     let db_url: &str = &env::var("DATABASE_URL").expect("DATABASE_URL not present.");
     let pool = PgPool::connect(db_url)
         .await
@@ -41,9 +51,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let state = State::default();
 
+    let db_pool = Arc::new(pool);
+
+    let events = AccountEvents::default();
+
+    tokio::spawn(scheduler::run(db_pool.clone(), events.clone()));
+
     let finance = FinanceControlService {
         state: state.clone(),
-        db_pool: Arc::new(pool),
+        db_pool: db_pool.clone(),
+        token_keys: token_keys.clone(),
+        events,
     };
 
     let admin = AdminService {
@@ -59,7 +77,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Server running!");
 
     Server::builder()
-        .layer(AuthorizationLayer::default())
+        .layer(AuthorizationLayer::new(token_keys, db_pool))
         .add_service(reflection)
         .add_service(AdminServer::new(admin))
         .add_service(FinanceControlServer::new(finance))
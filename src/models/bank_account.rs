@@ -3,12 +3,14 @@ use std::fmt;
 use chrono::Utc;
 use sqlx::{
     postgres::{PgRow, Postgres},
-    FromRow, Row,
+    Executor, FromRow, PgConnection, Row,
 };
 use thiserror::Error;
 use uuid::Uuid;
 
-use crate::models::transaction::{Transaction, TransactionType};
+use crate::models::currency::Currency;
+use crate::models::money::Money;
+use crate::models::transaction::{Transaction, TransactionStatus, TransactionType};
 
 #[derive(Error, Debug)]
 enum BankAccountErrorType {
@@ -18,6 +20,8 @@ enum BankAccountErrorType {
     UserIdParse,
     #[error("The account don't have enough funds to complete the transaction")]
     NotEnoughFunds,
+    #[error("The resulting balance is out of the supported range")]
+    BalanceOverflow,
 }
 
 #[derive(Debug, Error)]
@@ -41,6 +45,10 @@ impl BankAccountError {
     pub fn not_enough_funds() -> Self {
         BankAccountError::new(BankAccountErrorType::NotEnoughFunds)
     }
+
+    pub fn balance_overflow() -> Self {
+        BankAccountError::new(BankAccountErrorType::BalanceOverflow)
+    }
 }
 
 impl std::fmt::Display for BankAccountError {
@@ -82,7 +90,12 @@ impl fmt::Display for AccountType {
 pub struct BankAccount {
     pub id: Uuid,
     pub name: String,
-    pub balance: f64,
+    /// Sum of the posted transactions, as recorded by the ledger.
+    pub balance: Money,
+    /// The balance minus the amounts held by pending OUTCOME transactions.
+    pub available_balance: Money,
+    /// Both balances and all the transactions are in this currency.
+    pub currency: Currency,
     pub account_type: AccountType,
     pub user_id: Uuid,
     pub created_at: String,
@@ -91,7 +104,8 @@ pub struct BankAccount {
 impl BankAccount {
     pub fn new(
         name: String,
-        balance: f64,
+        balance: Money,
+        currency: Currency,
         account_type: AccountType,
         user_id: String,
     ) -> Result<BankAccount, BankAccountError> {
@@ -102,35 +116,172 @@ impl BankAccount {
             id: Uuid::new_v4(),
             name,
             balance,
+            available_balance: balance,
+            currency,
             account_type,
             user_id,
             created_at: Utc::now().to_rfc3339(),
         })
     }
 
+    /// Applies a new transaction. A pending OUTCOME only holds its amount
+    /// out of the available balance and a pending INCOME changes nothing
+    /// until it is posted. OUTCOMEs are checked against the available
+    /// balance, so held funds can't be spent twice.
     pub fn update_balance(&mut self, transaction: &Transaction) -> Result<(), BankAccountError> {
         match transaction.transaction_type {
             TransactionType::OUTCOME => {
-                if self.balance < transaction.amount {
+                if self.available_balance < transaction.amount {
                     return Err(BankAccountError::not_enough_funds());
                 }
 
-                self.balance -= transaction.amount;
+                self.available_balance = self
+                    .available_balance
+                    .checked_sub(transaction.amount)
+                    .map_err(|_err| BankAccountError::balance_overflow())?;
+
+                if transaction.status == TransactionStatus::POSTED {
+                    self.balance = self
+                        .balance
+                        .checked_sub(transaction.amount)
+                        .map_err(|_err| BankAccountError::balance_overflow())?;
+                }
 
                 Ok(())
             }
             TransactionType::INCOME => {
-                self.balance += transaction.amount;
+                if transaction.status == TransactionStatus::POSTED {
+                    self.credit(transaction.amount)?;
+                }
+
                 Ok(())
             }
         }
     }
 
+    /// Applies a pending transaction that is being posted. The amount of an
+    /// OUTCOME is already held, so only the balance changes.
+    pub fn settle(&mut self, transaction: &Transaction) -> Result<(), BankAccountError> {
+        match transaction.transaction_type {
+            TransactionType::OUTCOME => {
+                self.balance = self
+                    .balance
+                    .checked_sub(transaction.amount)
+                    .map_err(|_err| BankAccountError::balance_overflow())?;
+            }
+            TransactionType::INCOME => self.credit(transaction.amount)?,
+        }
+
+        Ok(())
+    }
+
+    /// Releases the hold of a pending transaction that is being voided.
+    pub fn release(&mut self, transaction: &Transaction) -> Result<(), BankAccountError> {
+        if transaction.transaction_type == TransactionType::OUTCOME {
+            self.available_balance = self
+                .available_balance
+                .checked_add(transaction.amount)
+                .map_err(|_err| BankAccountError::balance_overflow())?;
+        }
+
+        Ok(())
+    }
+
+    fn credit(&mut self, amount: Money) -> Result<(), BankAccountError> {
+        self.balance = self
+            .balance
+            .checked_add(amount)
+            .map_err(|_err| BankAccountError::balance_overflow())?;
+        self.available_balance = self
+            .available_balance
+            .checked_add(amount)
+            .map_err(|_err| BankAccountError::balance_overflow())?;
+
+        Ok(())
+    }
+
+    pub async fn find<'c, E>(executor: E, id: Uuid) -> Result<Option<BankAccount>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT a.id, a.name, a.balance, a.available_balance, a.currency, c.exponent,
+                      a.type, a.user_id, a.created_at::text
+               FROM bank_accounts a
+               JOIN currencies c ON c.code = a.currency
+               WHERE a.id = $1"#;
+
+        sqlx::query(query)
+            .bind(id)
+            .fetch_optional(executor)
+            .await?
+            .map(BankAccount::from_pg_row)
+            .transpose()
+    }
+
+    pub async fn list_by_user<'c, E>(
+        executor: E,
+        user_id: Uuid,
+    ) -> Result<Vec<BankAccount>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT a.id, a.name, a.balance, a.available_balance, a.currency, c.exponent,
+                      a.type::text AS type, a.user_id, a.created_at::text
+               FROM bank_accounts a
+               JOIN currencies c ON c.code = a.currency
+               WHERE a.user_id = $1
+               ORDER BY a.created_at, a.id"#;
+
+        sqlx::query_as::<_, BankAccount>(query)
+            .bind(user_id)
+            .fetch_all(executor)
+            .await
+    }
+
+    /// Loads the account and locks its row, but not the one of its currency,
+    /// until the surrounding DB transaction ends.
+    pub async fn find_for_update(
+        conn: &mut PgConnection,
+        id: Uuid,
+    ) -> Result<Option<BankAccount>, sqlx::Error> {
+        let query = r#"SELECT a.id, a.name, a.balance, a.available_balance, a.currency, c.exponent,
+                      a.type, a.user_id, a.created_at::text
+               FROM bank_accounts a
+               JOIN currencies c ON c.code = a.currency
+               WHERE a.id = $1
+               FOR UPDATE OF a"#;
+
+        sqlx::query(query)
+            .bind(id)
+            .fetch_optional(conn)
+            .await?
+            .map(BankAccount::from_pg_row)
+            .transpose()
+    }
+
+    pub async fn save_balance(&self, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+        let query = r#"
+            UPDATE bank_accounts
+            SET balance = $1, available_balance = $2
+            WHERE id = $3::uuid
+        "#;
+
+        sqlx::query(query)
+            .bind(self.balance)
+            .bind(self.available_balance)
+            .bind(self.id)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
     pub fn from_pg_row(row: PgRow) -> Result<Self, sqlx::Error> {
         let id: Uuid = row.get("id");
         let name: String = row.get("name");
-        let balance_in_cents: i64 = row.try_get("balance")?;
-        let balance: f64 = balance_in_cents as f64 / 100.0;
+        let balance: Money = row.try_get("balance")?;
+        let available_balance: Money = row.try_get("available_balance")?;
+        let currency = Currency::new(row.try_get("currency")?, row.try_get("exponent")?);
         let account_type: AccountType = row.get("type");
         let user_id: Uuid = row.get("user_id");
         let created_at: String = row.get("created_at");
@@ -139,6 +290,8 @@ impl BankAccount {
             id,
             name,
             balance,
+            available_balance,
+            currency,
             account_type,
             user_id,
             created_at,
@@ -154,8 +307,9 @@ where
     fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
         let id: Uuid = row.try_get("id")?;
         let name: String = row.try_get("name")?;
-        let balance_in_cents: i64 = row.try_get("balance")?;
-        let balance: f64 = balance_in_cents as f64 / 100.0;
+        let balance: Money = row.try_get("balance")?;
+        let available_balance: Money = row.try_get("available_balance")?;
+        let currency = Currency::new(row.try_get("currency")?, row.try_get("exponent")?);
         let created_at: String = row.try_get("created_at")?;
         let user_id: Uuid = row.try_get("user_id")?;
 
@@ -168,6 +322,8 @@ where
             id,
             name,
             balance,
+            available_balance,
+            currency,
             account_type,
             user_id,
             created_at,
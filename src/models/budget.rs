@@ -0,0 +1,376 @@
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use sqlx::{
+    postgres::{PgRow, Postgres},
+    Executor, PgConnection, Row,
+};
+use thiserror::Error;
+use tonic::Status;
+use uuid::Uuid;
+
+use crate::models::money::Money;
+use crate::models::transaction::{Transaction, TransactionType};
+use crate::tracing::error;
+
+/// Percentages of the limit that raise an alert when a transaction crosses
+/// them, in ascending order.
+const ALERT_THRESHOLDS: [i32; 2] = [80, 100];
+
+#[derive(Error, Debug)]
+pub enum BudgetError {
+    #[error("Invalid month, expected YYYY-MM")]
+    InvalidMonth,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<BudgetError> for Status {
+    fn from(err: BudgetError) -> Self {
+        match err {
+            BudgetError::InvalidMonth => Status::invalid_argument(err.to_string()),
+            BudgetError::Database(err) => {
+                error!("Error while handling the budget: {:?}", err);
+                Status::internal("Internal server error")
+            }
+        }
+    }
+}
+
+/// A calendar month in UTC, identified by its first day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Month(NaiveDate);
+
+impl Month {
+    pub fn current() -> Self {
+        Month::containing(Utc::now().date_naive())
+    }
+
+    pub fn containing(date: NaiveDate) -> Self {
+        Month(date.with_day(1).unwrap_or(date))
+    }
+
+    /// Parses `YYYY-MM`.
+    pub fn parse(raw: &str) -> Result<Self, BudgetError> {
+        NaiveDate::parse_from_str(&format!("{}-01", raw), "%Y-%m-%d")
+            .map(Month)
+            .map_err(|_err| BudgetError::InvalidMonth)
+    }
+
+    /// Start and exclusive end, as timestamps the queries can bind.
+    fn range(&self) -> (String, String) {
+        let end = self.0 + Months::new(1);
+
+        (
+            self.0.and_time(Default::default()).to_string(),
+            end.and_time(Default::default()).to_string(),
+        )
+    }
+}
+
+impl std::fmt::Display for Month {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.format("%Y-%m"))
+    }
+}
+
+/// A monthly spending limit on a category, which also covers its
+/// subcategories. Only the accounts in its currency count.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub category_id: Uuid,
+    pub currency: String,
+    pub limit: Money,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// The spending of one month against a budget.
+#[derive(Debug, Clone)]
+pub struct BudgetStatus {
+    pub budget: Budget,
+    pub month: Month,
+    pub spent: Money,
+}
+
+impl BudgetStatus {
+    /// Negative once the limit is exceeded.
+    pub fn remaining(&self) -> Money {
+        // Both are non-negative, so the difference can't overflow.
+        self.budget
+            .limit
+            .checked_sub(self.spent)
+            .unwrap_or_default()
+    }
+
+    pub fn percent_used(&self) -> i32 {
+        percent_of(self.spent, self.budget.limit)
+    }
+}
+
+/// Raised when a transaction takes the spending of a budget past one of the
+/// alert thresholds. Only the highest threshold crossed is reported.
+#[derive(Debug, Clone)]
+pub struct BudgetAlert {
+    pub status: BudgetStatus,
+    pub threshold_percent: i32,
+}
+
+fn percent_of(amount: Money, limit: Money) -> i32 {
+    let percent = amount.to_wire() as i128 * 100 / (limit.to_wire() as i128).max(1);
+
+    percent.clamp(i32::MIN as i128, i32::MAX as i128) as i32
+}
+
+fn crossed_threshold(limit: Money, before: Money, after: Money) -> Option<i32> {
+    let (limit, before, after) = (
+        limit.to_wire() as i128,
+        before.to_wire() as i128,
+        after.to_wire() as i128,
+    );
+
+    ALERT_THRESHOLDS.into_iter().rev().find(|&threshold| {
+        let line = limit * threshold as i128;
+        before * 100 < line && after * 100 >= line
+    })
+}
+
+impl Budget {
+    pub fn new(user_id: Uuid, category_id: Uuid, currency: String, limit: Money) -> Self {
+        let now = Utc::now().to_rfc3339();
+
+        Budget {
+            id: Uuid::new_v4(),
+            user_id,
+            category_id,
+            currency,
+            limit,
+            created_at: now.clone(),
+            updated_at: now,
+        }
+    }
+
+    fn from_pg_row(row: PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Budget {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            category_id: row.try_get("category_id")?,
+            currency: row.try_get("currency")?,
+            limit: row.try_get("limit_amount")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    /// Creates the budget, or changes the limit of the one the user already
+    /// has on the category in the currency. Returns the stored budget.
+    pub async fn upsert<'c, E>(&self, executor: E) -> Result<Budget, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"INSERT INTO budgets (id, user_id, category_id, currency, limit_amount, created_at, updated_at)
+               VALUES ($1, $2, $3, $4, $5, $6::timestamp, $7::timestamp)
+               ON CONFLICT (user_id, category_id, currency)
+               DO UPDATE SET limit_amount = EXCLUDED.limit_amount, updated_at = EXCLUDED.updated_at
+               RETURNING id, user_id, category_id, currency, limit_amount, created_at::text, updated_at::text"#;
+
+        sqlx::query(query)
+            .bind(self.id)
+            .bind(self.user_id)
+            .bind(self.category_id)
+            .bind(&self.currency)
+            .bind(self.limit)
+            .bind(&self.created_at)
+            .bind(&self.updated_at)
+            .fetch_one(executor)
+            .await
+            .and_then(Budget::from_pg_row)
+    }
+
+    pub async fn delete_for_category<'c, E>(
+        executor: E,
+        user_id: Uuid,
+        category_id: Uuid,
+        currency: &str,
+    ) -> Result<(), sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        sqlx::query(
+            "DELETE FROM budgets WHERE user_id = $1 AND category_id = $2 AND currency = $3",
+        )
+        .bind(user_id)
+        .bind(category_id)
+        .bind(currency)
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_by_user<'c, E>(executor: E, user_id: Uuid) -> Result<Vec<Budget>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT id, user_id, category_id, currency, limit_amount, created_at::text,
+                      updated_at::text
+               FROM budgets
+               WHERE user_id = $1
+               ORDER BY created_at, id"#;
+
+        sqlx::query(query)
+            .bind(user_id)
+            .fetch_all(executor)
+            .await?
+            .into_iter()
+            .map(Budget::from_pg_row)
+            .collect()
+    }
+
+    /// Locks the budgets of the user in the currency on the category or any
+    /// of its parents.
+    async fn lock_covering(
+        conn: &mut PgConnection,
+        user_id: Uuid,
+        category_id: &str,
+        currency: &str,
+    ) -> Result<Vec<Budget>, sqlx::Error> {
+        let query = r#"WITH RECURSIVE ancestors AS (
+                   SELECT id, parent_id FROM categories WHERE id = $2::uuid
+                   UNION
+                   SELECT c.id, c.parent_id FROM categories c
+                   JOIN ancestors a ON c.id = a.parent_id
+               )
+               SELECT id, user_id, category_id, currency, limit_amount, created_at::text,
+                      updated_at::text
+               FROM budgets
+               WHERE user_id = $1
+                 AND currency = $3
+                 AND category_id IN (SELECT id FROM ancestors)
+               ORDER BY id
+               FOR UPDATE"#;
+
+        sqlx::query(query)
+            .bind(user_id)
+            .bind(category_id)
+            .bind(currency)
+            .fetch_all(conn)
+            .await?
+            .into_iter()
+            .map(Budget::from_pg_row)
+            .collect()
+    }
+}
+
+/// Spending of every budget of the user over the month, counting the
+/// OUTCOME transactions of the budget category and all its subcategories on
+/// the accounts in the budget currency. Pending transactions count; voided
+/// ones, reversed ones and reversals are left out.
+pub async fn statuses<'c, E>(
+    executor: E,
+    user_id: Uuid,
+    month: Month,
+) -> Result<Vec<BudgetStatus>, sqlx::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let (from, to) = month.range();
+
+    let query = r#"WITH RECURSIVE covered AS (
+               SELECT b.id AS budget_id, b.category_id, b.currency FROM budgets b
+               WHERE b.user_id = $1
+               UNION
+               SELECT covered.budget_id, c.id, covered.currency FROM categories c
+               JOIN covered ON c.parent_id = covered.category_id
+           ),
+           spent AS (
+               SELECT covered.budget_id, SUM(t.amount) AS amount
+               FROM covered
+               JOIN transactions t ON t.category_id = covered.category_id
+               JOIN bank_accounts a ON a.id = t.origin_account_id
+               WHERE a.user_id = $1
+                 AND a.currency = covered.currency
+                 AND t.transaction_type = 'OUTCOME'
+                 AND t.transfer_id IS NULL
+                 AND t.status <> 'VOIDED'
+                 AND t.reversal_of IS NULL
+                 AND NOT EXISTS (SELECT 1 FROM transactions r WHERE r.reversal_of = t.id)
+                 AND t.created_at >= $2::timestamp
+                 AND t.created_at < $3::timestamp
+               GROUP BY covered.budget_id
+           )
+           SELECT b.id, b.user_id, b.category_id, b.currency, b.limit_amount, b.created_at::text,
+                  b.updated_at::text, COALESCE(spent.amount, 0)::bigint AS spent
+           FROM budgets b
+           LEFT JOIN spent ON spent.budget_id = b.id
+           WHERE b.user_id = $1
+           ORDER BY b.created_at, b.id"#;
+
+    sqlx::query(query)
+        .bind(user_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(executor)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let spent = row.try_get("spent")?;
+
+            Ok(BudgetStatus {
+                budget: Budget::from_pg_row(row)?,
+                month,
+                spent,
+            })
+        })
+        .collect()
+}
+
+/// Checks the budgets covering a transaction that was just inserted on an
+/// account in `currency`. The budgets stay locked until commit, so
+/// concurrent transactions of the same user see each other's spending and
+/// each threshold is crossed only once.
+pub async fn check_alerts(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    currency: &str,
+    transaction: &Transaction,
+) -> Result<Vec<BudgetAlert>, sqlx::Error> {
+    let Some(category_id) = &transaction.category_id else {
+        return Ok(Vec::new());
+    };
+
+    if transaction.transaction_type != TransactionType::OUTCOME
+        || transaction.transfer_id.is_some()
+        || transaction.reversal_of.is_some()
+    {
+        return Ok(Vec::new());
+    }
+
+    let covering = Budget::lock_covering(&mut *conn, user_id, category_id, currency).await?;
+
+    if covering.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let month = DateTime::parse_from_rfc3339(&transaction.created_at)
+        .map(|created_at| Month::containing(created_at.with_timezone(&Utc).date_naive()))
+        .unwrap_or_else(|_err| Month::current());
+
+    let alerts = statuses(&mut *conn, user_id, month)
+        .await?
+        .into_iter()
+        .filter(|status| covering.iter().any(|budget| budget.id == status.budget.id))
+        .filter_map(|status| {
+            let before = status.spent.checked_sub(transaction.amount).ok()?;
+
+            crossed_threshold(status.budget.limit, before, status.spent).map(|threshold_percent| {
+                BudgetAlert {
+                    status,
+                    threshold_percent,
+                }
+            })
+        })
+        .collect();
+
+    Ok(alerts)
+}
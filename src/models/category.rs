@@ -0,0 +1,282 @@
+use chrono::Utc;
+use sqlx::{
+    postgres::{PgRow, Postgres},
+    Executor, PgConnection, Row,
+};
+use thiserror::Error;
+use tonic::Status;
+use uuid::Uuid;
+
+use crate::tracing::error;
+
+const MAX_NAME_LENGTH: usize = 255;
+
+#[derive(Error, Debug)]
+pub enum CategoryError {
+    #[error("Category not found")]
+    NotFound,
+    #[error("The category name must have between 1 and 255 characters")]
+    InvalidName,
+    #[error("A category with this name already exists at this level")]
+    NameTaken,
+    #[error("System categories can't be changed")]
+    ReadOnly,
+    #[error("A category can't be moved under itself or one of its subcategories")]
+    Cycle,
+    #[error("The category still has subcategories")]
+    HasSubcategories,
+    #[error("The category is still used by transactions, scheduled transactions, categorization rules or budgets")]
+    InUse,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<CategoryError> for Status {
+    fn from(err: CategoryError) -> Self {
+        match err {
+            CategoryError::NotFound => Status::not_found(err.to_string()),
+            CategoryError::InvalidName | CategoryError::NameTaken | CategoryError::Cycle => {
+                Status::invalid_argument(err.to_string())
+            }
+            CategoryError::ReadOnly => Status::permission_denied(err.to_string()),
+            CategoryError::HasSubcategories | CategoryError::InUse => {
+                Status::failed_precondition(err.to_string())
+            }
+            CategoryError::Database(err) => {
+                error!("Error while handling the category: {:?}", err);
+                Status::internal("Internal server error")
+            }
+        }
+    }
+}
+
+fn map_unique_violation(err: sqlx::Error) -> CategoryError {
+    match err {
+        sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+            CategoryError::NameTaken
+        }
+        err => CategoryError::Database(err),
+    }
+}
+
+/// A transaction category. Categories without a `user_id` are the system
+/// defaults, visible to every user and read-only.
+#[derive(Debug, Clone)]
+pub struct Category {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub parent_id: Option<Uuid>,
+    pub name: String,
+    pub created_at: String,
+}
+
+impl Category {
+    pub fn new(user_id: Uuid, parent_id: Option<Uuid>, name: &str) -> Result<Self, CategoryError> {
+        let mut category = Category {
+            id: Uuid::new_v4(),
+            user_id: Some(user_id),
+            parent_id,
+            name: String::new(),
+            created_at: Utc::now().to_rfc3339(),
+        };
+        category.rename(name)?;
+
+        Ok(category)
+    }
+
+    pub fn rename(&mut self, name: &str) -> Result<(), CategoryError> {
+        let name = name.trim();
+
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(CategoryError::InvalidName);
+        }
+
+        self.name = name.to_owned();
+
+        Ok(())
+    }
+
+    pub fn is_system(&self) -> bool {
+        self.user_id.is_none()
+    }
+
+    fn from_pg_row(row: PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Category {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            parent_id: row.try_get("parent_id")?,
+            name: row.try_get("name")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+
+    pub async fn insert<'c, E>(&self, executor: E) -> Result<(), CategoryError>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"INSERT INTO categories (id, user_id, parent_id, name, created_at)
+               VALUES ($1, $2, $3, $4, $5::timestamp)"#;
+
+        sqlx::query(query)
+            .bind(self.id)
+            .bind(self.user_id)
+            .bind(self.parent_id)
+            .bind(&self.name)
+            .bind(&self.created_at)
+            .execute(executor)
+            .await
+            .map_err(map_unique_violation)?;
+
+        Ok(())
+    }
+
+    pub async fn update<'c, E>(&self, executor: E) -> Result<(), CategoryError>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"UPDATE categories SET name = $2, parent_id = $3 WHERE id = $1"#;
+
+        sqlx::query(query)
+            .bind(self.id)
+            .bind(&self.name)
+            .bind(self.parent_id)
+            .execute(executor)
+            .await
+            .map_err(map_unique_violation)?;
+
+        Ok(())
+    }
+
+    /// Finds a category of the user or a system one.
+    pub async fn find_visible<'c, E>(
+        executor: E,
+        user_id: Uuid,
+        id: Uuid,
+    ) -> Result<Option<Category>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT id, user_id, parent_id, name, created_at::text
+               FROM categories
+               WHERE id = $2 AND (user_id = $1 OR user_id IS NULL)"#;
+
+        sqlx::query(query)
+            .bind(user_id)
+            .bind(id)
+            .fetch_optional(executor)
+            .await?
+            .map(Category::from_pg_row)
+            .transpose()
+    }
+
+    /// Lists the system categories followed by the ones of the user.
+    pub async fn list_visible<'c, E>(
+        executor: E,
+        user_id: Uuid,
+    ) -> Result<Vec<Category>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT id, user_id, parent_id, name, created_at::text
+               FROM categories
+               WHERE user_id = $1 OR user_id IS NULL
+               ORDER BY user_id NULLS FIRST, name, id"#;
+
+        sqlx::query(query)
+            .bind(user_id)
+            .fetch_all(executor)
+            .await?
+            .into_iter()
+            .map(Category::from_pg_row)
+            .collect()
+    }
+
+    /// Returns which of the ids are categories the user can assign.
+    pub async fn visible_ids<'c, E>(
+        executor: E,
+        user_id: Uuid,
+        ids: &[Uuid],
+    ) -> Result<Vec<Uuid>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT id FROM categories
+               WHERE id = ANY($2) AND (user_id = $1 OR user_id IS NULL)"#;
+
+        sqlx::query_scalar(query)
+            .bind(user_id)
+            .bind(ids)
+            .fetch_all(executor)
+            .await
+    }
+
+    /// Whether the category `id` is `ancestor_id` itself or one of its
+    /// subcategories, at any depth.
+    pub async fn is_within<'c, E>(
+        executor: E,
+        id: Uuid,
+        ancestor_id: Uuid,
+    ) -> Result<bool, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"WITH RECURSIVE ancestors AS (
+                   SELECT id, parent_id FROM categories WHERE id = $1
+                   UNION
+                   SELECT c.id, c.parent_id FROM categories c
+                   JOIN ancestors a ON c.id = a.parent_id
+               )
+               SELECT EXISTS (SELECT 1 FROM ancestors WHERE id = $2)"#;
+
+        sqlx::query_scalar(query)
+            .bind(id)
+            .bind(ancestor_id)
+            .fetch_one(executor)
+            .await
+    }
+
+    /// Deletes the category. It can't be deleted while transactions, active
+    /// scheduled transactions, rules or budgets use it, so posted
+    /// transactions are only ever recategorized by an amendment. Ended and
+    /// cancelled schedules become uncategorized. Run in a DB transaction: the
+    /// category is locked so no schedule starts using it meanwhile.
+    pub async fn delete(&self, conn: &mut PgConnection) -> Result<(), CategoryError> {
+        let scheduled_query = r#"SELECT EXISTS (
+                   SELECT 1 FROM scheduled_transactions s
+                   WHERE s.category_id = c.id AND s.next_occurrence_at IS NOT NULL
+               )
+               FROM categories c
+               WHERE c.id = $1
+               FOR UPDATE"#;
+
+        let scheduled: bool = sqlx::query_scalar(scheduled_query)
+            .bind(self.id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or(CategoryError::NotFound)?;
+
+        if scheduled {
+            return Err(CategoryError::InUse);
+        }
+
+        let query = r#"DELETE FROM categories
+               WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM categories WHERE parent_id = $1)"#;
+
+        let result = sqlx::query(query)
+            .bind(self.id)
+            .execute(conn)
+            .await
+            .map_err(|err| match err {
+                sqlx::Error::Database(ref db_err) if db_err.is_foreign_key_violation() => {
+                    CategoryError::InUse
+                }
+                err => CategoryError::Database(err),
+            })?;
+
+        if result.rows_affected() == 0 {
+            return Err(CategoryError::HasSubcategories);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use sqlx::{postgres::Postgres, Executor};
+use thiserror::Error;
+use tonic::Status;
+
+use crate::models::money::{Money, MoneyError};
+use crate::tracing::error;
+
+/// Currency of the accounts and budgets created without one.
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+#[derive(Error, Debug)]
+pub enum CurrencyError {
+    #[error("Unsupported currency, expected an ISO 4217 code such as EUR or USD")]
+    Unsupported,
+    #[error("The amount is in {found} but the account is in {expected}")]
+    Mismatch { expected: String, found: String },
+    #[error("Transfers between accounts in different currencies aren't supported")]
+    CrossCurrencyTransfer,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<CurrencyError> for Status {
+    fn from(err: CurrencyError) -> Self {
+        match err {
+            CurrencyError::Database(err) => {
+                error!("Error while handling the currency: {:?}", err);
+                Status::internal("Internal server error")
+            }
+            err => Status::invalid_argument(err.to_string()),
+        }
+    }
+}
+
+/// An ISO 4217 currency and the number of decimal places of its minor unit,
+/// e.g. 2 for EUR and 0 for JPY.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Currency {
+    pub code: String,
+    pub exponent: u32,
+}
+
+impl Currency {
+    /// Normalizes a code given by a client; an empty one means `default`.
+    pub fn normalize_code(raw: &str, default: &str) -> Result<String, CurrencyError> {
+        let code = raw.trim();
+
+        if code.is_empty() {
+            return Ok(default.to_owned());
+        }
+
+        if code.len() != 3 || !code.bytes().all(|byte| byte.is_ascii_alphabetic()) {
+            return Err(CurrencyError::Unsupported);
+        }
+
+        Ok(code.to_ascii_uppercase())
+    }
+
+    pub async fn find<'c, E>(executor: E, code: &str) -> Result<Option<Currency>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let row: Option<(String, i16)> =
+            sqlx::query_as("SELECT code, exponent FROM currencies WHERE code = $1")
+                .bind(code)
+                .fetch_optional(executor)
+                .await?;
+
+        Ok(row.map(|(code, exponent)| Currency::new(code, exponent)))
+    }
+
+    pub fn new(code: String, exponent: i16) -> Self {
+        Currency {
+            code,
+            exponent: exponent.max(0) as u32,
+        }
+    }
+
+    /// Checks a currency given along with an amount for this account
+    /// currency. An empty one means the account currency.
+    pub fn ensure_matches(&self, raw: &str) -> Result<(), CurrencyError> {
+        let code = Currency::normalize_code(raw, &self.code)?;
+
+        if code != self.code {
+            return Err(CurrencyError::Mismatch {
+                expected: self.code.clone(),
+                found: code,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn parse_amount(&self, raw: &str) -> Result<Money, MoneyError> {
+        Money::from_decimal(raw, self.exponent)
+    }
+
+    pub fn format_amount(&self, amount: Money) -> String {
+        amount.to_decimal(self.exponent)
+    }
+}
@@ -0,0 +1,174 @@
+use prost::Message;
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, Row};
+use thiserror::Error;
+use tonic::{Request, Status};
+use uuid::Uuid;
+
+use crate::tracing::error;
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// How long a stored response is replayed for a repeated key.
+const RETENTION_HOURS: i32 = 24;
+
+const MAX_KEY_LENGTH: usize = 255;
+
+#[derive(Error, Debug)]
+pub enum IdempotencyError {
+    #[error("The idempotency key must be between 1 and 255 characters")]
+    InvalidKey,
+
+    #[error("The idempotency key was already used with a different request")]
+    PayloadMismatch,
+
+    #[error("A request with this idempotency key is still being processed")]
+    InProgress,
+
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<IdempotencyError> for Status {
+    fn from(err: IdempotencyError) -> Self {
+        match err {
+            IdempotencyError::InvalidKey => Status::invalid_argument(err.to_string()),
+            IdempotencyError::PayloadMismatch => Status::failed_precondition(err.to_string()),
+            IdempotencyError::InProgress => Status::aborted(err.to_string()),
+            IdempotencyError::Database(err) => {
+                error!("Error while handling the idempotency key: {:?}", err);
+                Status::internal("Internal server error")
+            }
+        }
+    }
+}
+
+/// A client supplied `idempotency-key`, scoped to the caller and the method
+/// and bound to a hash of the request payload.
+#[derive(Debug)]
+pub struct IdempotencyKey {
+    pub user_id: Uuid,
+    pub method: &'static str,
+    pub key: String,
+    pub request_hash: Vec<u8>,
+}
+
+impl IdempotencyKey {
+    pub fn from_request<T: Message>(
+        request: &Request<T>,
+        user_id: Uuid,
+        method: &'static str,
+    ) -> Result<Option<IdempotencyKey>, IdempotencyError> {
+        let Some(value) = request.metadata().get(IDEMPOTENCY_KEY_HEADER) else {
+            return Ok(None);
+        };
+
+        let key = value
+            .to_str()
+            .map_err(|_err| IdempotencyError::InvalidKey)?
+            .to_owned();
+
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+            return Err(IdempotencyError::InvalidKey);
+        }
+
+        let request_hash = Sha256::digest(request.get_ref().encode_to_vec()).to_vec();
+
+        Ok(Some(IdempotencyKey {
+            user_id,
+            method,
+            key,
+            request_hash,
+        }))
+    }
+
+    /// Claims the key inside the caller's DB transaction. Returns the stored
+    /// response when the same request was already completed. A concurrent
+    /// request with the same key blocks here until the first one commits or
+    /// rolls back.
+    pub async fn claim<R: Message + Default>(
+        &self,
+        conn: &mut PgConnection,
+    ) -> Result<Option<R>, IdempotencyError> {
+        let delete_expired_query = r#"
+            DELETE FROM idempotency_keys
+            WHERE user_id = $1 AND method = $2 AND key = $3
+              AND created_at < NOW() - make_interval(hours => $4)
+        "#;
+
+        sqlx::query(delete_expired_query)
+            .bind(self.user_id)
+            .bind(self.method)
+            .bind(&self.key)
+            .bind(RETENTION_HOURS)
+            .execute(&mut *conn)
+            .await?;
+
+        let insert_query = r#"
+            INSERT INTO idempotency_keys (user_id, method, key, request_hash)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+        "#;
+
+        let inserted = sqlx::query(insert_query)
+            .bind(self.user_id)
+            .bind(self.method)
+            .bind(&self.key)
+            .bind(&self.request_hash)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+
+        if inserted == 1 {
+            return Ok(None);
+        }
+
+        let select_query = r#"
+            SELECT request_hash, response FROM idempotency_keys
+            WHERE user_id = $1 AND method = $2 AND key = $3
+        "#;
+
+        let row = sqlx::query(select_query)
+            .bind(self.user_id)
+            .bind(self.method)
+            .bind(&self.key)
+            .fetch_one(&mut *conn)
+            .await?;
+
+        let request_hash: Vec<u8> = row.try_get("request_hash")?;
+        let response: Option<Vec<u8>> = row.try_get("response")?;
+
+        if request_hash != self.request_hash {
+            return Err(IdempotencyError::PayloadMismatch);
+        }
+
+        let response = response.ok_or(IdempotencyError::InProgress)?;
+
+        R::decode(response.as_slice())
+            .map(Some)
+            .map_err(|err| IdempotencyError::Database(sqlx::Error::Decode(Box::new(err))))
+    }
+
+    /// Stores the response so later requests with this key replay it. Must
+    /// run in the same DB transaction as `claim`.
+    pub async fn complete<R: Message>(
+        &self,
+        conn: &mut PgConnection,
+        response: &R,
+    ) -> Result<(), IdempotencyError> {
+        let query = r#"
+            UPDATE idempotency_keys SET response = $4
+            WHERE user_id = $1 AND method = $2 AND key = $3
+        "#;
+
+        sqlx::query(query)
+            .bind(self.user_id)
+            .bind(self.method)
+            .bind(&self.key)
+            .bind(response.encode_to_vec())
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use sqlx::{postgres::Postgres, Executor, PgConnection, QueryBuilder};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::models::bank_account::BankAccount;
+use crate::models::money::Money;
+use crate::models::transaction::{Transaction, TransactionType, INSERT_BATCH_SIZE};
+
+#[derive(Error, Debug)]
+pub enum LedgerError {
+    #[error("The journal entry postings don't sum to zero")]
+    Unbalanced,
+
+    #[error("The balance of account {account_id} doesn't match its ledger")]
+    BalanceMismatch { account_id: Uuid },
+
+    #[error("The available balance of account {account_id} doesn't match its holds")]
+    AvailableBalanceMismatch { account_id: Uuid },
+
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// One side of a journal entry. Positive amounts debit and negative amounts
+/// credit the account; `None` is the outside world.
+#[derive(Debug)]
+pub struct Posting {
+    pub bank_account_id: Option<String>,
+    pub amount: Money,
+}
+
+#[derive(Debug)]
+pub struct JournalEntry {
+    pub id: String,
+    pub description: Option<String>,
+    pub postings: Vec<Posting>,
+    pub created_at: String,
+}
+
+impl JournalEntry {
+    /// An INCOME debits the account and credits the outside world; an
+    /// OUTCOME does the opposite.
+    pub fn for_transaction(transaction: &Transaction) -> JournalEntry {
+        let account_amount = match transaction.transaction_type {
+            TransactionType::INCOME => transaction.amount,
+            TransactionType::OUTCOME => -transaction.amount,
+        };
+
+        JournalEntry {
+            id: transaction.journal_entry_id.clone(),
+            description: transaction.description.clone(),
+            postings: vec![
+                Posting {
+                    bank_account_id: Some(transaction.origin_account_id.clone()),
+                    amount: account_amount,
+                },
+                Posting {
+                    bank_account_id: None,
+                    amount: -account_amount,
+                },
+            ],
+            created_at: transaction.created_at.clone(),
+        }
+    }
+
+    /// A transfer moves money between two accounts without touching the
+    /// outside world.
+    pub fn for_transfer(debit: &Transaction, credit: &Transaction) -> JournalEntry {
+        JournalEntry {
+            id: debit.journal_entry_id.clone(),
+            description: debit.description.clone(),
+            postings: vec![
+                Posting {
+                    bank_account_id: Some(debit.origin_account_id.clone()),
+                    amount: -debit.amount,
+                },
+                Posting {
+                    bank_account_id: Some(credit.origin_account_id.clone()),
+                    amount: credit.amount,
+                },
+            ],
+            created_at: debit.created_at.clone(),
+        }
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.postings
+            .iter()
+            .try_fold(Money::default(), |total, posting| {
+                total.checked_add(posting.amount)
+            })
+            .map(|total| total.is_zero())
+            .unwrap_or(false)
+    }
+
+    pub async fn insert(&self, conn: &mut PgConnection) -> Result<(), LedgerError> {
+        JournalEntry::insert_many(conn, std::slice::from_ref(self)).await
+    }
+
+    /// Inserts the entry without its postings, for a pending transaction.
+    /// They are made with `insert_postings` once it is posted.
+    pub async fn insert_pending(&self, conn: &mut PgConnection) -> Result<(), LedgerError> {
+        JournalEntry::insert_headers(conn, std::slice::from_ref(self)).await
+    }
+
+    /// Inserts the postings of an entry already inserted by
+    /// `insert_pending`.
+    pub async fn insert_postings(&self, conn: &mut PgConnection) -> Result<(), LedgerError> {
+        if !self.is_balanced() {
+            return Err(LedgerError::Unbalanced);
+        }
+
+        JournalEntry::insert_postings_many(conn, std::slice::from_ref(self)).await
+    }
+
+    /// Inserts the journal entries and their postings with multi-row
+    /// INSERTs. Nothing is written unless every entry is balanced.
+    pub async fn insert_many(
+        conn: &mut PgConnection,
+        entries: &[JournalEntry],
+    ) -> Result<(), LedgerError> {
+        if !entries.iter().all(JournalEntry::is_balanced) {
+            return Err(LedgerError::Unbalanced);
+        }
+
+        JournalEntry::insert_headers(conn, entries).await?;
+        JournalEntry::insert_postings_many(conn, entries).await
+    }
+
+    async fn insert_headers(
+        conn: &mut PgConnection,
+        entries: &[JournalEntry],
+    ) -> Result<(), LedgerError> {
+        for chunk in entries.chunks(INSERT_BATCH_SIZE) {
+            let mut query = QueryBuilder::<Postgres>::new(
+                "INSERT INTO journal_entries (id, description, created_at) ",
+            );
+
+            query.push_values(chunk, |mut row, entry| {
+                row.push_bind(&entry.id)
+                    .push_unseparated("::uuid")
+                    .push_bind(&entry.description)
+                    .push_bind(&entry.created_at)
+                    .push_unseparated("::timestamp");
+            });
+
+            query.build().execute(&mut *conn).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn insert_postings_many(
+        conn: &mut PgConnection,
+        entries: &[JournalEntry],
+    ) -> Result<(), LedgerError> {
+        let postings: Vec<(&JournalEntry, &Posting)> = entries
+            .iter()
+            .flat_map(|entry| entry.postings.iter().map(move |posting| (entry, posting)))
+            .collect();
+
+        for chunk in postings.chunks(INSERT_BATCH_SIZE) {
+            let mut query = QueryBuilder::<Postgres>::new(
+                "INSERT INTO ledger_entries (id, journal_entry_id, bank_account_id, amount, created_at) ",
+            );
+
+            query.push_values(chunk, |mut row, (entry, posting)| {
+                row.push_bind(Uuid::new_v4())
+                    .push_bind(&entry.id)
+                    .push_unseparated("::uuid")
+                    .push_bind(&posting.bank_account_id)
+                    .push_unseparated("::uuid")
+                    .push_bind(posting.amount)
+                    .push_bind(&entry.created_at)
+                    .push_unseparated("::timestamp");
+            });
+
+            query.build().execute(&mut *conn).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Balance of the account from its ledger postings made strictly before the
+/// given timestamp.
+pub async fn balance_before<'c, E>(
+    executor: E,
+    account_id: Uuid,
+    before: &str,
+) -> Result<Money, sqlx::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let query = r#"SELECT COALESCE(SUM(amount), 0)::bigint FROM ledger_entries
+           WHERE bank_account_id = $1 AND created_at < $2::timestamp"#;
+
+    sqlx::query_scalar(query)
+        .bind(account_id)
+        .bind(before)
+        .fetch_one(executor)
+        .await
+}
+
+/// Checks the stored balance of the account against the running total of
+/// its ledger postings, which the database keeps as they are inserted, and
+/// its available balance against the balance minus the pending OUTCOMEs.
+/// Run after saving the balance; it catches any drift, including the one
+/// left by earlier writes.
+pub async fn verify_balance(
+    conn: &mut PgConnection,
+    account: &BankAccount,
+) -> Result<(), LedgerError> {
+    let query = r#"SELECT balance, available_balance, ledger_balance FROM bank_accounts
+           WHERE id = $1"#;
+
+    let (balance, available_balance, ledger_balance): (Money, Money, Money) = sqlx::query_as(query)
+        .bind(account.id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    if balance != ledger_balance {
+        return Err(LedgerError::BalanceMismatch {
+            account_id: account.id,
+        });
+    }
+
+    let holds_query = r#"SELECT COALESCE(SUM(amount), 0)::bigint FROM transactions
+           WHERE origin_account_id = $1 AND status = 'PENDING' AND transaction_type = 'OUTCOME'"#;
+
+    let held: Money = sqlx::query_scalar(holds_query)
+        .bind(account.id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    if balance.checked_sub(held).ok() != Some(available_balance) {
+        return Err(LedgerError::AvailableBalanceMismatch {
+            account_id: account.id,
+        });
+    }
+
+    Ok(())
+}
@@ -1,3 +1,13 @@
 pub mod bank_account;
+pub mod budget;
+pub mod category;
+pub mod currency;
+pub mod idempotency;
+pub mod ledger;
+pub mod money;
+pub mod report;
+pub mod rule;
+pub mod schedule;
+pub mod tag;
 pub mod transaction;
 pub mod user;
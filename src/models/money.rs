@@ -0,0 +1,196 @@
+use std::ops::Neg;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MoneyError {
+    #[error("The amount is out of the supported range")]
+    Overflow,
+    #[error("The amount can't be negative")]
+    Negative,
+    #[error("Invalid amount, expected a decimal with at most {0} decimal places")]
+    InvalidDecimal(u32),
+}
+
+/// An exact amount of money expressed in minor units of the currency of its
+/// account (cents for EUR, yen for JPY). It maps directly onto the BIGINT
+/// amount columns so values never pass through floating point.
+///
+/// `from_wire`/`to_wire` are the only conversions between the proto int64
+/// fields and this type, and both sides use the same minor units.
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[sqlx(transparent)]
+pub struct Money(i64);
+
+impl Money {
+    pub fn from_wire(minor: i64) -> Result<Money, MoneyError> {
+        if minor < 0 {
+            return Err(MoneyError::Negative);
+        }
+
+        Ok(Money(minor))
+    }
+
+    /// Parses a non-negative decimal such as `12.3` or `1500.00`, as written
+    /// in bank statements, into minor units of a currency with `exponent`
+    /// decimal places.
+    pub fn from_decimal(raw: &str, exponent: u32) -> Result<Money, MoneyError> {
+        let (major, fraction) = raw.split_once('.').unwrap_or((raw, ""));
+
+        let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+
+        if major.is_empty()
+            || fraction.len() > exponent as usize
+            || !is_digits(major)
+            || !is_digits(fraction)
+        {
+            return Err(MoneyError::InvalidDecimal(exponent));
+        }
+
+        let major: i64 = major.parse().map_err(|_err| MoneyError::Overflow)?;
+        let fraction: i64 = format!("{:0<width$}", fraction, width = exponent as usize)
+            .parse()
+            .unwrap_or(0);
+
+        major
+            .checked_mul(10_i64.pow(exponent))
+            .and_then(|minor| minor.checked_add(fraction))
+            .map(Money)
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// Formats the amount as a decimal with `exponent` decimal places, the
+    /// inverse of `from_decimal`.
+    pub fn to_decimal(self, exponent: u32) -> String {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let minor = self.0.unsigned_abs();
+        let units = 10_u64.pow(exponent);
+
+        if exponent == 0 {
+            return format!("{}{}", sign, minor);
+        }
+
+        format!(
+            "{}{}.{:0width$}",
+            sign,
+            minor / units,
+            minor % units,
+            width = exponent as usize
+        )
+    }
+
+    pub fn to_wire(self) -> i64 {
+        self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError> {
+        self.0
+            .checked_add(other.0)
+            .map(Money)
+            .ok_or(MoneyError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, MoneyError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Money)
+            .ok_or(MoneyError::Overflow)
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_negative_wire_amounts() {
+        assert!(matches!(Money::from_wire(-1), Err(MoneyError::Negative)));
+        assert_eq!(Money::from_wire(0).unwrap().to_wire(), 0);
+    }
+
+    #[test]
+    fn round_trips_decimals() {
+        for (raw, exponent, minor, formatted) in [
+            ("1500", 0, 1500, "1500"),
+            ("12.30", 2, 1230, "12.30"),
+            ("12.3", 2, 1230, "12.30"),
+            ("0.05", 2, 5, "0.05"),
+            ("1.234", 3, 1234, "1.234"),
+            ("7", 3, 7000, "7.000"),
+        ] {
+            let amount = Money::from_decimal(raw, exponent).unwrap();
+
+            assert_eq!(amount.to_wire(), minor, "{}", raw);
+            assert_eq!(amount.to_decimal(exponent), formatted);
+            assert_eq!(Money::from_decimal(formatted, exponent).unwrap(), amount);
+        }
+    }
+
+    #[test]
+    fn rejects_too_many_decimal_places() {
+        assert!(matches!(
+            Money::from_decimal("1.5", 0),
+            Err(MoneyError::InvalidDecimal(0))
+        ));
+        assert!(matches!(
+            Money::from_decimal("12.345", 2),
+            Err(MoneyError::InvalidDecimal(2))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_decimals() {
+        for raw in ["", ".5", "-1.00", "1,00", "1.0a", "+1"] {
+            assert!(
+                matches!(
+                    Money::from_decimal(raw, 2),
+                    Err(MoneyError::InvalidDecimal(2))
+                ),
+                "{}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn detects_overflow_at_the_i64_limit() {
+        let max = Money::from_decimal("92233720368547758.07", 2).unwrap();
+
+        assert_eq!(max.to_wire(), i64::MAX);
+        assert!(matches!(
+            Money::from_decimal("92233720368547758.08", 2),
+            Err(MoneyError::Overflow)
+        ));
+        assert!(matches!(
+            Money::from_decimal("99999999999999999999", 0),
+            Err(MoneyError::Overflow)
+        ));
+        assert!(matches!(
+            max.checked_add(Money(1)),
+            Err(MoneyError::Overflow)
+        ));
+        assert!(matches!(
+            (-max).checked_sub(Money(2)),
+            Err(MoneyError::Overflow)
+        ));
+    }
+
+    #[test]
+    fn formats_negative_amounts_below_one_unit() {
+        assert_eq!((-Money(5)).to_decimal(2), "-0.05");
+        assert_eq!((-Money(1230)).to_decimal(2), "-12.30");
+        assert_eq!((-Money(15)).to_decimal(0), "-15");
+    }
+}
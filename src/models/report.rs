@@ -0,0 +1,206 @@
+use sqlx::{
+    postgres::{PgRow, Postgres},
+    Executor, Row,
+};
+use uuid::Uuid;
+
+use crate::models::money::Money;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Month,
+    Week,
+    Year,
+}
+
+impl Period {
+    pub fn from_proto(value: &i32) -> Result<Self, String> {
+        match value {
+            0 => Ok(Period::Month),
+            1 => Ok(Period::Week),
+            2 => Ok(Period::Year),
+            _ => Err("Invalid summary period".to_owned()),
+        }
+    }
+
+    /// Field name given to `date_trunc`. Weeks start on Monday.
+    fn date_trunc_field(&self) -> &'static str {
+        match self {
+            Period::Month => "month",
+            Period::Week => "week",
+            Period::Year => "year",
+        }
+    }
+}
+
+/// Totals of one account over one period bucket, in the account currency.
+/// `external_*` leave out the transfers, which only move money between the
+/// accounts of the same user. The totals leave out the reversed
+/// transactions and their reversals, as budgets do; `net_change` doesn't,
+/// so the balances still follow the ledger when a reversal falls in a later
+/// bucket.
+#[derive(Debug)]
+pub struct AccountActivity {
+    pub account_id: String,
+    pub currency: String,
+    pub bucket: String,
+    pub income: Money,
+    pub outcome: Money,
+    pub external_income: Money,
+    pub external_outcome: Money,
+    pub net_change: Money,
+}
+
+impl AccountActivity {
+    fn from_pg_row(row: PgRow) -> Result<Self, sqlx::Error> {
+        Ok(AccountActivity {
+            account_id: row.try_get("account_id")?,
+            currency: row.try_get("currency")?,
+            bucket: row.try_get("bucket")?,
+            income: row.try_get("income")?,
+            outcome: row.try_get("outcome")?,
+            external_income: row.try_get("external_income")?,
+            external_outcome: row.try_get("external_outcome")?,
+            net_change: row.try_get("net_change")?,
+        })
+    }
+}
+
+/// Aggregates the posted transactions of every account of the user per
+/// period bucket, ordered by bucket and currency. Buckets without
+/// transactions are left out.
+pub async fn account_activity<'c, E>(
+    executor: E,
+    user_id: Uuid,
+    period: Period,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Vec<AccountActivity>, sqlx::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let query = r#"SELECT t.origin_account_id::text AS account_id,
+                  a.currency,
+                  date_trunc($2, t.created_at)::text AS bucket,
+                  COALESCE(SUM(t.amount) FILTER (WHERE t.transaction_type = 'INCOME' AND c.counted), 0)::bigint AS income,
+                  COALESCE(SUM(t.amount) FILTER (WHERE t.transaction_type = 'OUTCOME' AND c.counted), 0)::bigint AS outcome,
+                  COALESCE(SUM(t.amount) FILTER (WHERE t.transaction_type = 'INCOME' AND t.transfer_id IS NULL AND c.counted), 0)::bigint AS external_income,
+                  COALESCE(SUM(t.amount) FILTER (WHERE t.transaction_type = 'OUTCOME' AND t.transfer_id IS NULL AND c.counted), 0)::bigint AS external_outcome,
+                  SUM(CASE WHEN t.transaction_type = 'INCOME' THEN t.amount ELSE -t.amount END)::bigint AS net_change
+           FROM transactions t
+           JOIN bank_accounts a ON a.id = t.origin_account_id
+           CROSS JOIN LATERAL (
+               SELECT t.reversal_of IS NULL
+                  AND NOT EXISTS (SELECT 1 FROM transactions r WHERE r.reversal_of = t.id) AS counted
+           ) c
+           WHERE a.user_id = $1
+             AND t.status = 'POSTED'
+             AND ($3::timestamp IS NULL OR t.created_at >= $3::timestamp)
+             AND ($4::timestamp IS NULL OR t.created_at < $4::timestamp)
+           GROUP BY 1, 2, 3
+           ORDER BY 3, 2, 1"#;
+
+    sqlx::query(query)
+        .bind(user_id)
+        .bind(period.date_trunc_field())
+        .bind(from)
+        .bind(to)
+        .fetch_all(executor)
+        .await?
+        .into_iter()
+        .map(AccountActivity::from_pg_row)
+        .collect()
+}
+
+/// Balance of every account of the user right before `from`, with the
+/// account currency, for the accounts that had transactions by then.
+pub async fn balances_before<'c, E>(
+    executor: E,
+    user_id: Uuid,
+    from: &str,
+) -> Result<Vec<(String, String, Money)>, sqlx::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let query = r#"SELECT t.origin_account_id::text,
+                  a.currency,
+                  SUM(CASE WHEN t.transaction_type = 'INCOME' THEN t.amount ELSE -t.amount END)::bigint
+           FROM transactions t
+           JOIN bank_accounts a ON a.id = t.origin_account_id
+           WHERE a.user_id = $1 AND t.status = 'POSTED' AND t.created_at < $2::timestamp
+           GROUP BY 1, 2"#;
+
+    sqlx::query_as(query)
+        .bind(user_id)
+        .bind(from)
+        .fetch_all(executor)
+        .await
+}
+
+/// Totals of one category over one period bucket in one currency, transfers
+/// and reversed transactions apart.
+#[derive(Debug)]
+pub struct CategoryActivity {
+    pub bucket: String,
+    pub currency: String,
+    pub category_id: Option<String>,
+    pub income: Money,
+    pub outcome: Money,
+    pub transaction_count: i64,
+}
+
+impl CategoryActivity {
+    fn from_pg_row(row: PgRow) -> Result<Self, sqlx::Error> {
+        Ok(CategoryActivity {
+            bucket: row.try_get("bucket")?,
+            currency: row.try_get("currency")?,
+            category_id: row.try_get("category_id")?,
+            income: row.try_get("income")?,
+            outcome: row.try_get("outcome")?,
+            transaction_count: row.try_get("transaction_count")?,
+        })
+    }
+}
+
+/// Aggregates the posted transactions of every account of the user per
+/// period bucket, currency and category, ordered by bucket and currency.
+/// Reversed transactions and their reversals are left out.
+pub async fn category_activity<'c, E>(
+    executor: E,
+    user_id: Uuid,
+    period: Period,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Vec<CategoryActivity>, sqlx::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let query = r#"SELECT date_trunc($2, t.created_at)::text AS bucket,
+                  a.currency,
+                  t.category_id::text AS category_id,
+                  COALESCE(SUM(t.amount) FILTER (WHERE t.transaction_type = 'INCOME'), 0)::bigint AS income,
+                  COALESCE(SUM(t.amount) FILTER (WHERE t.transaction_type = 'OUTCOME'), 0)::bigint AS outcome,
+                  COUNT(*) AS transaction_count
+           FROM transactions t
+           JOIN bank_accounts a ON a.id = t.origin_account_id
+           WHERE a.user_id = $1
+             AND t.transfer_id IS NULL
+             AND t.status = 'POSTED'
+             AND t.reversal_of IS NULL
+             AND NOT EXISTS (SELECT 1 FROM transactions r WHERE r.reversal_of = t.id)
+             AND ($3::timestamp IS NULL OR t.created_at >= $3::timestamp)
+             AND ($4::timestamp IS NULL OR t.created_at < $4::timestamp)
+           GROUP BY 1, 2, 3
+           ORDER BY 1, 2, 3"#;
+
+    sqlx::query(query)
+        .bind(user_id)
+        .bind(period.date_trunc_field())
+        .bind(from)
+        .bind(to)
+        .fetch_all(executor)
+        .await?
+        .into_iter()
+        .map(CategoryActivity::from_pg_row)
+        .collect()
+}
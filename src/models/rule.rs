@@ -0,0 +1,360 @@
+use chrono::Utc;
+use regex::{Regex, RegexBuilder};
+use sqlx::{
+    postgres::{PgRow, Postgres},
+    Executor, Row,
+};
+use thiserror::Error;
+use tonic::Status;
+use uuid::Uuid;
+
+use crate::models::money::Money;
+use crate::models::transaction::{Transaction, TransactionType};
+use crate::tracing::error;
+
+const MAX_CONTAINS_LENGTH: usize = 255;
+const MAX_PATTERN_LENGTH: usize = 1024;
+
+/// Bound on the compiled size of a pattern, so a single rule can't make
+/// every transaction of the user expensive to evaluate.
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+#[derive(Error, Debug)]
+pub enum RuleError {
+    #[error("Rule not found")]
+    NotFound,
+    #[error("A rule needs at least one condition")]
+    NoCondition,
+    #[error("A rule must assign a category or at least one tag")]
+    NoAction,
+    #[error("The description text must have between 1 and 255 characters")]
+    InvalidContains,
+    #[error("Invalid description pattern: {0}")]
+    InvalidPattern(String),
+    #[error("The minimum amount can't be greater than the maximum")]
+    InvalidAmountRange,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<RuleError> for Status {
+    fn from(err: RuleError) -> Self {
+        match err {
+            RuleError::NotFound => Status::not_found(err.to_string()),
+            RuleError::Database(err) => {
+                error!("Error while handling the rule: {:?}", err);
+                Status::internal("Internal server error")
+            }
+            _ => Status::invalid_argument(err.to_string()),
+        }
+    }
+}
+
+fn compile_pattern(pattern: &str) -> Result<Regex, RuleError> {
+    if pattern.len() > MAX_PATTERN_LENGTH {
+        return Err(RuleError::InvalidPattern(format!(
+            "at most {} characters are allowed",
+            MAX_PATTERN_LENGTH
+        )));
+    }
+
+    RegexBuilder::new(pattern)
+        .size_limit(PATTERN_SIZE_LIMIT)
+        .build()
+        .map_err(|err| RuleError::InvalidPattern(err.to_string()))
+}
+
+/// A categorization rule of a user. Every condition that is set must hold
+/// for the rule to match a transaction.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// Rules run by ascending priority.
+    pub priority: i32,
+    /// Case-insensitive.
+    pub description_contains: Option<String>,
+    pub description_pattern: Option<String>,
+    pub min_amount: Option<Money>,
+    pub max_amount: Option<Money>,
+    pub account_id: Option<Uuid>,
+    pub transaction_type: Option<TransactionType>,
+    pub category_id: Option<Uuid>,
+    /// Normalized with `tag::normalize`.
+    pub tags: Vec<String>,
+    pub created_at: String,
+}
+
+impl Rule {
+    pub fn new(user_id: Uuid, priority: i32) -> Self {
+        Rule {
+            id: Uuid::new_v4(),
+            user_id,
+            priority,
+            description_contains: None,
+            description_pattern: None,
+            min_amount: None,
+            max_amount: None,
+            account_id: None,
+            transaction_type: None,
+            category_id: None,
+            tags: Vec::new(),
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), RuleError> {
+        let has_condition = self.description_contains.is_some()
+            || self.description_pattern.is_some()
+            || self.min_amount.is_some()
+            || self.max_amount.is_some()
+            || self.account_id.is_some()
+            || self.transaction_type.is_some();
+
+        if !has_condition {
+            return Err(RuleError::NoCondition);
+        }
+
+        if self.category_id.is_none() && self.tags.is_empty() {
+            return Err(RuleError::NoAction);
+        }
+
+        if let Some(contains) = &self.description_contains {
+            if contains.is_empty() || contains.chars().count() > MAX_CONTAINS_LENGTH {
+                return Err(RuleError::InvalidContains);
+            }
+        }
+
+        if let (Some(min_amount), Some(max_amount)) = (self.min_amount, self.max_amount) {
+            if min_amount > max_amount {
+                return Err(RuleError::InvalidAmountRange);
+            }
+        }
+
+        if let Some(pattern) = &self.description_pattern {
+            compile_pattern(pattern)?;
+        }
+
+        Ok(())
+    }
+
+    fn matches(&self, pattern: Option<&Regex>, transaction: &Transaction) -> bool {
+        let description = transaction.description.as_deref();
+
+        if let Some(contains) = &self.description_contains {
+            let found = description.is_some_and(|description| {
+                description
+                    .to_lowercase()
+                    .contains(&contains.to_lowercase())
+            });
+
+            if !found {
+                return false;
+            }
+        }
+
+        if let Some(pattern) = pattern {
+            if !description.is_some_and(|description| pattern.is_match(description)) {
+                return false;
+            }
+        }
+
+        self.min_amount
+            .is_none_or(|min_amount| transaction.amount >= min_amount)
+            && self
+                .max_amount
+                .is_none_or(|max_amount| transaction.amount <= max_amount)
+            && self
+                .account_id
+                .is_none_or(|account_id| transaction.origin_account_id == account_id.to_string())
+            && self
+                .transaction_type
+                .as_ref()
+                .is_none_or(|transaction_type| &transaction.transaction_type == transaction_type)
+    }
+
+    fn from_pg_row(row: PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Rule {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            priority: row.try_get("priority")?,
+            description_contains: row.try_get("description_contains")?,
+            description_pattern: row.try_get("description_pattern")?,
+            min_amount: row.try_get("min_amount")?,
+            max_amount: row.try_get("max_amount")?,
+            account_id: row.try_get("account_id")?,
+            transaction_type: row.try_get("transaction_type")?,
+            category_id: row.try_get("category_id")?,
+            tags: row.try_get("tags")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+
+    pub async fn insert<'c, E>(&self, executor: E) -> Result<(), sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"INSERT INTO categorization_rules (id, user_id, priority, description_contains,
+                   description_pattern, min_amount, max_amount, account_id, transaction_type,
+                   category_id, tags, created_at)
+               VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::transactiontype, $10, $11, $12::timestamp)"#;
+
+        sqlx::query(query)
+            .bind(self.id)
+            .bind(self.user_id)
+            .bind(self.priority)
+            .bind(&self.description_contains)
+            .bind(&self.description_pattern)
+            .bind(self.min_amount)
+            .bind(self.max_amount)
+            .bind(self.account_id)
+            .bind(
+                self.transaction_type
+                    .as_ref()
+                    .map(|transaction_type| transaction_type.to_string()),
+            )
+            .bind(self.category_id)
+            .bind(&self.tags)
+            .bind(&self.created_at)
+            .execute(executor)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_owned<'c, E>(
+        executor: E,
+        user_id: Uuid,
+        id: Uuid,
+    ) -> Result<Option<Rule>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT id, user_id, priority, description_contains, description_pattern,
+                      min_amount, max_amount, account_id, transaction_type, category_id, tags,
+                      created_at::text
+               FROM categorization_rules
+               WHERE id = $2 AND user_id = $1"#;
+
+        sqlx::query(query)
+            .bind(user_id)
+            .bind(id)
+            .fetch_optional(executor)
+            .await?
+            .map(Rule::from_pg_row)
+            .transpose()
+    }
+
+    /// Lists the rules of the user in the order they run.
+    pub async fn list_by_user<'c, E>(executor: E, user_id: Uuid) -> Result<Vec<Rule>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT id, user_id, priority, description_contains, description_pattern,
+                      min_amount, max_amount, account_id, transaction_type, category_id, tags,
+                      created_at::text
+               FROM categorization_rules
+               WHERE user_id = $1
+               ORDER BY priority, created_at, id"#;
+
+        sqlx::query(query)
+            .bind(user_id)
+            .fetch_all(executor)
+            .await?
+            .into_iter()
+            .map(Rule::from_pg_row)
+            .collect()
+    }
+
+    pub async fn delete<'c, E>(&self, executor: E) -> Result<(), sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        sqlx::query("DELETE FROM categorization_rules WHERE id = $1")
+            .bind(self.id)
+            .execute(executor)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// The rules of a user, with their patterns compiled once for every
+/// transaction they are run against.
+pub struct RuleSet {
+    rules: Vec<(Rule, Option<Regex>)>,
+}
+
+impl RuleSet {
+    /// Expects the rules in the order they run, as `Rule::list_by_user`
+    /// returns them.
+    pub fn new(rules: Vec<Rule>) -> Result<Self, RuleError> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                let pattern = rule
+                    .description_pattern
+                    .as_deref()
+                    .map(compile_pattern)
+                    .transpose()?;
+
+                Ok((rule, pattern))
+            })
+            .collect::<Result<_, RuleError>>()?;
+
+        Ok(RuleSet { rules })
+    }
+
+    pub async fn load<'c, E>(executor: E, user_id: Uuid) -> Result<Self, RuleError>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        RuleSet::new(Rule::list_by_user(executor, user_id).await?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        self.rules
+            .iter()
+            .any(|(rule, pattern)| rule.matches(pattern.as_ref(), transaction))
+    }
+
+    /// Assigns the category of the first matching rule that has one and adds
+    /// the tags of every matching rule. A category already set on the
+    /// transaction is only replaced when `overwrite_category` is given.
+    /// Returns whether the transaction changed.
+    pub fn apply(&self, transaction: &mut Transaction, overwrite_category: bool) -> bool {
+        let mut changed = false;
+        let mut category_assigned = !overwrite_category && transaction.category_id.is_some();
+
+        for (rule, pattern) in &self.rules {
+            if !rule.matches(pattern.as_ref(), transaction) {
+                continue;
+            }
+
+            if let Some(category_id) = rule.category_id.filter(|_| !category_assigned) {
+                let category_id = Some(category_id.to_string());
+                category_assigned = true;
+
+                if transaction.category_id != category_id {
+                    transaction.category_id = category_id;
+                    changed = true;
+                }
+            }
+
+            for tag in &rule.tags {
+                if !transaction.tags.contains(tag) {
+                    transaction.tags.push(tag.clone());
+                    changed = true;
+                }
+            }
+        }
+
+        transaction.tags.sort();
+
+        changed
+    }
+}
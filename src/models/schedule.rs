@@ -0,0 +1,709 @@
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime, SubsecRound, TimeDelta, Utc};
+use sqlx::{
+    postgres::{PgRow, Postgres},
+    Executor, PgConnection, Row,
+};
+use thiserror::Error;
+use tonic::Status;
+use uuid::Uuid;
+
+use crate::models::money::Money;
+use crate::models::transaction::{Transaction, TransactionType};
+use crate::pagination::TIMESTAMP_FORMAT;
+use crate::tracing::error;
+
+const MAX_INTERVAL: i32 = 1000;
+
+/// How far in the past a schedule may start. Occurrences already due when
+/// the schedule is created are posted right away.
+const MAX_START_DELAY: TimeDelta = TimeDelta::days(1);
+
+/// Bounds of the delay before retrying an occurrence that failed. The delay
+/// is as long as the occurrence has been due, so it doubles on every retry.
+const MIN_RETRY_DELAY: TimeDelta = TimeDelta::minutes(1);
+const MAX_RETRY_DELAY: TimeDelta = TimeDelta::days(1);
+
+/// Stored as the last error of a schedule whose occurrence failed for
+/// another reason than a refusal; the cause is only logged.
+const RETRY_ERROR: &str = "The occurrence couldn't be posted and will be retried";
+
+#[derive(Error, Debug)]
+pub enum ScheduleError {
+    #[error("Scheduled transaction not found")]
+    NotFound,
+    #[error("Invalid timestamp, expected RFC 3339")]
+    InvalidTimestamp,
+    #[error("A schedule can't start more than a day in the past")]
+    StartTooEarly,
+    #[error("Invalid frequency")]
+    InvalidFrequency,
+    #[error("The interval must be between 1 and 1000")]
+    InvalidInterval,
+    #[error("The day of the month must be between 1 and 31, for monthly schedules only")]
+    InvalidDayOfMonth,
+    #[error("The occurrence count must be greater than zero")]
+    InvalidCount,
+    #[error("The schedule ends before its first occurrence")]
+    NoOccurrence,
+    #[error("The schedule has already ended")]
+    Ended,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<ScheduleError> for Status {
+    fn from(err: ScheduleError) -> Self {
+        match err {
+            ScheduleError::NotFound => Status::not_found(err.to_string()),
+            ScheduleError::Ended => Status::failed_precondition(err.to_string()),
+            ScheduleError::Database(err) => {
+                error!("Error while handling the scheduled transaction: {:?}", err);
+                Status::internal("Internal server error")
+            }
+            _ => Status::invalid_argument(err.to_string()),
+        }
+    }
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "schedulefrequency", rename_all = "UPPERCASE")]
+pub enum Frequency {
+    Once,
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Frequency {
+    pub fn from_proto(value: &i32) -> Result<Self, ScheduleError> {
+        match value {
+            0 => Ok(Frequency::Once),
+            1 => Ok(Frequency::Daily),
+            2 => Ok(Frequency::Weekly),
+            3 => Ok(Frequency::Monthly),
+            _ => Err(ScheduleError::InvalidFrequency),
+        }
+    }
+
+    pub fn to_proto(self) -> i32 {
+        match self {
+            Frequency::Once => 0,
+            Frequency::Daily => 1,
+            Frequency::Weekly => 2,
+            Frequency::Monthly => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    /// Every `interval` days, weeks or months.
+    pub interval: i32,
+    /// Monthly schedules only. Months without that day use their last one.
+    pub day_of_month: Option<i32>,
+    /// Inclusive.
+    pub ends_at: Option<NaiveDateTime>,
+    pub max_occurrences: Option<i32>,
+}
+
+impl Recurrence {
+    pub fn once() -> Self {
+        Recurrence {
+            frequency: Frequency::Once,
+            interval: 1,
+            day_of_month: None,
+            ends_at: None,
+            max_occurrences: None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), ScheduleError> {
+        if !(1..=MAX_INTERVAL).contains(&self.interval) {
+            return Err(ScheduleError::InvalidInterval);
+        }
+
+        if let Some(day_of_month) = self.day_of_month {
+            if self.frequency != Frequency::Monthly || !(1..=31).contains(&day_of_month) {
+                return Err(ScheduleError::InvalidDayOfMonth);
+            }
+        }
+
+        if self.max_occurrences.is_some_and(|count| count < 1) {
+            return Err(ScheduleError::InvalidCount);
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses an RFC 3339 timestamp into UTC.
+pub fn parse_timestamp(raw: &str) -> Result<NaiveDateTime, ScheduleError> {
+    chrono::DateTime::parse_from_rfc3339(raw)
+        .map(|timestamp| timestamp.naive_utc())
+        .map_err(|_err| ScheduleError::InvalidTimestamp)
+}
+
+fn last_day_of_month(date: NaiveDate) -> u32 {
+    (date.with_day(1).unwrap_or(date) + Months::new(1) - Days::new(1)).day()
+}
+
+/// The `day` of the month of `date`, or its last day when it is shorter.
+fn clamp_to_month(date: NaiveDate, day: u32) -> NaiveDate {
+    date.with_day(day.min(last_day_of_month(date)))
+        .unwrap_or(date)
+}
+
+/// A transaction posted by the scheduler on each occurrence of its
+/// recurrence, through the same path as `ExecuteTransaction`.
+#[derive(Debug, Clone)]
+pub struct ScheduledTransaction {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub account_id: Uuid,
+    pub amount: Money,
+    pub transaction_type: TransactionType,
+    pub description: Option<String>,
+    pub category_id: Option<Uuid>,
+    pub tags: Vec<String>,
+    pub recurrence: Recurrence,
+    pub starts_at: NaiveDateTime,
+    /// Occurrences handled so far, posted or failed.
+    pub occurrences: i32,
+    /// When the current occurrence is handled: when it is due, or later
+    /// when it is retried after an error.
+    pub next_occurrence_at: Option<NaiveDateTime>,
+    /// Why the last occurrence couldn't be posted, if it couldn't.
+    pub last_error: Option<String>,
+    pub cancelled_at: Option<String>,
+    pub created_at: String,
+}
+
+impl ScheduledTransaction {
+    /// Schedules a copy of `template`, which carries the amount, type,
+    /// description, category and tags to post.
+    pub fn new(
+        user_id: Uuid,
+        template: &Transaction,
+        starts_at: NaiveDateTime,
+        recurrence: Recurrence,
+    ) -> Result<Self, ScheduleError> {
+        // Postgres keeps microseconds.
+        let starts_at = starts_at.trunc_subsecs(6);
+        let now = Utc::now().naive_utc();
+
+        if starts_at < now - MAX_START_DELAY {
+            return Err(ScheduleError::StartTooEarly);
+        }
+
+        recurrence.validate()?;
+
+        let account_id =
+            Uuid::try_parse(&template.origin_account_id).map_err(|_err| ScheduleError::NotFound)?;
+        let category_id = template
+            .category_id
+            .as_deref()
+            .map(Uuid::try_parse)
+            .transpose()
+            .map_err(|_err| ScheduleError::NotFound)?;
+
+        let mut schedule = ScheduledTransaction {
+            id: Uuid::new_v4(),
+            user_id,
+            account_id,
+            amount: template.amount,
+            transaction_type: template.transaction_type.clone(),
+            description: template.description.clone(),
+            category_id,
+            tags: template.tags.clone(),
+            recurrence,
+            starts_at,
+            occurrences: 0,
+            next_occurrence_at: None,
+            last_error: None,
+            cancelled_at: None,
+            created_at: now.to_string(),
+        };
+
+        schedule.next_occurrence_at = schedule.occurrence_at(0);
+
+        if schedule.next_occurrence_at.is_none() {
+            return Err(ScheduleError::NoOccurrence);
+        }
+
+        Ok(schedule)
+    }
+
+    /// When the occurrence number `index`, counting from 0, is due. `None`
+    /// past the end of the schedule.
+    pub fn occurrence_at(&self, index: i32) -> Option<NaiveDateTime> {
+        let recurrence = &self.recurrence;
+
+        if recurrence
+            .max_occurrences
+            .is_some_and(|count| index >= count)
+        {
+            return None;
+        }
+
+        let steps = u32::try_from(index)
+            .ok()?
+            .checked_mul(recurrence.interval as u32)?;
+
+        let occurrence = match recurrence.frequency {
+            Frequency::Once if index == 0 => self.starts_at,
+            Frequency::Once => return None,
+            Frequency::Daily => self.starts_at.checked_add_days(Days::new(steps as u64))?,
+            Frequency::Weekly => self
+                .starts_at
+                .checked_add_days(Days::new(steps as u64 * 7))?,
+            Frequency::Monthly => {
+                let start = self.starts_at.date();
+                let day = recurrence
+                    .day_of_month
+                    .map_or(start.day(), |day| day as u32);
+
+                // Counted from the first month that has an occurrence on
+                // or after the start, so short months don't shift the
+                // following ones.
+                let mut first_month = start.with_day(1)?;
+                if clamp_to_month(start, day).and_time(self.starts_at.time()) < self.starts_at {
+                    first_month = first_month.checked_add_months(Months::new(1))?;
+                }
+
+                let month = first_month.checked_add_months(Months::new(steps))?;
+
+                clamp_to_month(month, day).and_time(self.starts_at.time())
+            }
+        };
+
+        if recurrence
+            .ends_at
+            .is_some_and(|ends_at| occurrence > ends_at)
+        {
+            return None;
+        }
+
+        Some(occurrence)
+    }
+
+    /// When the current occurrence is due. It identifies the occurrence,
+    /// even when it is retried later.
+    fn due_at(&self) -> Option<NaiveDateTime> {
+        self.occurrence_at(self.occurrences)
+    }
+
+    /// Moves on to the next occurrence once the current one was handled.
+    pub fn advance(&mut self) {
+        self.occurrences += 1;
+        self.next_occurrence_at = self.occurrence_at(self.occurrences);
+    }
+
+    fn from_pg_row(row: PgRow) -> Result<Self, sqlx::Error> {
+        let parse = |column: &str| -> Result<Option<NaiveDateTime>, sqlx::Error> {
+            row.try_get::<Option<String>, _>(column)?
+                .map(|raw| {
+                    NaiveDateTime::parse_from_str(&raw, TIMESTAMP_FORMAT)
+                        .map_err(|err| sqlx::Error::Decode(Box::new(err)))
+                })
+                .transpose()
+        };
+
+        Ok(ScheduledTransaction {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            account_id: row.try_get("account_id")?,
+            amount: row.try_get("amount")?,
+            transaction_type: row.try_get("transaction_type")?,
+            description: row.try_get("description")?,
+            category_id: row.try_get("category_id")?,
+            tags: row.try_get("tags")?,
+            recurrence: Recurrence {
+                frequency: row.try_get("frequency")?,
+                interval: row.try_get("interval")?,
+                day_of_month: row.try_get("day_of_month")?,
+                ends_at: parse("ends_at")?,
+                max_occurrences: row.try_get("max_occurrences")?,
+            },
+            starts_at: parse("starts_at")?.ok_or_else(|| {
+                sqlx::Error::Decode("scheduled transaction without a start".into())
+            })?,
+            occurrences: row.try_get("occurrences")?,
+            next_occurrence_at: parse("next_occurrence_at")?,
+            last_error: row.try_get("last_error")?,
+            cancelled_at: row.try_get("cancelled_at")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+
+    pub async fn insert<'c, E>(&self, executor: E) -> Result<(), sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"INSERT INTO scheduled_transactions (id, user_id, account_id, amount,
+                   transaction_type, description, category_id, tags, frequency, interval,
+                   day_of_month, starts_at, ends_at, max_occurrences, occurrences,
+                   next_occurrence_at, created_at)
+               VALUES ($1, $2, $3, $4, $5::transactiontype, $6, $7, $8, $9, $10, $11,
+                       $12::timestamp, $13::timestamp, $14, $15, $16::timestamp, $17::timestamp)"#;
+
+        sqlx::query(query)
+            .bind(self.id)
+            .bind(self.user_id)
+            .bind(self.account_id)
+            .bind(self.amount)
+            .bind(self.transaction_type.to_string())
+            .bind(&self.description)
+            .bind(self.category_id)
+            .bind(&self.tags)
+            .bind(self.recurrence.frequency)
+            .bind(self.recurrence.interval)
+            .bind(self.recurrence.day_of_month)
+            .bind(self.starts_at.to_string())
+            .bind(self.recurrence.ends_at.map(|ends_at| ends_at.to_string()))
+            .bind(self.recurrence.max_occurrences)
+            .bind(self.occurrences)
+            .bind(self.next_occurrence_at.map(|next| next.to_string()))
+            .bind(&self.created_at)
+            .execute(executor)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_owned<'c, E>(
+        executor: E,
+        user_id: Uuid,
+        id: Uuid,
+    ) -> Result<Option<ScheduledTransaction>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT id, user_id, account_id, amount, transaction_type, description,
+                      category_id, tags, frequency, interval, day_of_month, starts_at::text,
+                      ends_at::text, max_occurrences, occurrences, next_occurrence_at::text,
+                      last_error, cancelled_at::text, created_at::text
+               FROM scheduled_transactions
+               WHERE id = $2 AND user_id = $1"#;
+
+        sqlx::query(query)
+            .bind(user_id)
+            .bind(id)
+            .fetch_optional(executor)
+            .await?
+            .map(ScheduledTransaction::from_pg_row)
+            .transpose()
+    }
+
+    pub async fn list_by_user<'c, E>(
+        executor: E,
+        user_id: Uuid,
+    ) -> Result<Vec<ScheduledTransaction>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT id, user_id, account_id, amount, transaction_type, description,
+                      category_id, tags, frequency, interval, day_of_month, starts_at::text,
+                      ends_at::text, max_occurrences, occurrences, next_occurrence_at::text,
+                      last_error, cancelled_at::text, created_at::text
+               FROM scheduled_transactions
+               WHERE user_id = $1
+               ORDER BY created_at, id"#;
+
+        sqlx::query(query)
+            .bind(user_id)
+            .fetch_all(executor)
+            .await?
+            .into_iter()
+            .map(ScheduledTransaction::from_pg_row)
+            .collect()
+    }
+
+    /// Locks the schedule with the earliest occurrence due by `now`. Skips the
+    /// ones locked by other scheduler instances.
+    pub async fn lock_next_due(
+        conn: &mut PgConnection,
+        now: NaiveDateTime,
+    ) -> Result<Option<ScheduledTransaction>, sqlx::Error> {
+        let query = r#"SELECT id, user_id, account_id, amount, transaction_type, description,
+                      category_id, tags, frequency, interval, day_of_month, starts_at::text,
+                      ends_at::text, max_occurrences, occurrences, next_occurrence_at::text,
+                      last_error, cancelled_at::text, created_at::text
+               FROM scheduled_transactions
+               WHERE next_occurrence_at <= $1::timestamp
+               ORDER BY next_occurrence_at
+               LIMIT 1
+               FOR UPDATE SKIP LOCKED"#;
+
+        sqlx::query(query)
+            .bind(now.to_string())
+            .fetch_optional(conn)
+            .await?
+            .map(ScheduledTransaction::from_pg_row)
+            .transpose()
+    }
+
+    /// Records that the current occurrence is being handled. Returns false
+    /// when it already was, in which case it must not be posted again.
+    pub async fn claim_occurrence(&self, conn: &mut PgConnection) -> Result<bool, sqlx::Error> {
+        let query = r#"INSERT INTO scheduled_occurrences (scheduled_transaction_id, occurrence_at)
+               VALUES ($1, $2::timestamp)
+               ON CONFLICT DO NOTHING"#;
+
+        let inserted = sqlx::query(query)
+            .bind(self.id)
+            .bind(self.due_at().map(|due_at| due_at.to_string()))
+            .execute(conn)
+            .await?
+            .rows_affected();
+
+        Ok(inserted == 1)
+    }
+
+    /// Stores the outcome of the claimed occurrence: the posted transaction
+    /// or why it couldn't be posted.
+    pub async fn finish_occurrence(
+        &mut self,
+        conn: &mut PgConnection,
+        outcome: Result<&str, String>,
+    ) -> Result<(), sqlx::Error> {
+        let (transaction_id, error) = match outcome {
+            Ok(transaction_id) => (Some(transaction_id), None),
+            Err(error) => (None, Some(error)),
+        };
+
+        let query = r#"UPDATE scheduled_occurrences SET transaction_id = $3::uuid, error = $4
+               WHERE scheduled_transaction_id = $1 AND occurrence_at = $2::timestamp"#;
+
+        sqlx::query(query)
+            .bind(self.id)
+            .bind(self.due_at().map(|due_at| due_at.to_string()))
+            .bind(transaction_id)
+            .bind(&error)
+            .execute(conn)
+            .await?;
+
+        self.last_error = error;
+
+        Ok(())
+    }
+
+    /// Saves the progress made with `advance` and `finish_occurrence`.
+    pub async fn save_progress(&self, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+        let query = r#"UPDATE scheduled_transactions
+               SET occurrences = $2, next_occurrence_at = $3::timestamp, last_error = $4
+               WHERE id = $1"#;
+
+        sqlx::query(query)
+            .bind(self.id)
+            .bind(self.occurrences)
+            .bind(self.next_occurrence_at.map(|next| next.to_string()))
+            .bind(&self.last_error)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Retries the current occurrence later, after it failed at `now` for
+    /// another reason than a refusal.
+    pub async fn postpone(
+        &mut self,
+        conn: &mut PgConnection,
+        now: NaiveDateTime,
+    ) -> Result<(), sqlx::Error> {
+        let due_at = self.due_at().unwrap_or(now);
+        let delay = (now - due_at).clamp(MIN_RETRY_DELAY, MAX_RETRY_DELAY);
+
+        self.next_occurrence_at = Some((now + delay).trunc_subsecs(6));
+        self.last_error = Some(RETRY_ERROR.to_owned());
+
+        self.save_progress(conn).await
+    }
+
+    /// Stops the schedule. Occurrences already posted are kept.
+    pub async fn cancel<'c, E>(&mut self, executor: E) -> Result<(), ScheduleError>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        if self.next_occurrence_at.is_none() {
+            return Err(ScheduleError::Ended);
+        }
+
+        let query = r#"UPDATE scheduled_transactions
+               SET next_occurrence_at = NULL, cancelled_at = CURRENT_TIMESTAMP
+               WHERE id = $1 AND next_occurrence_at IS NOT NULL
+               RETURNING cancelled_at::text"#;
+
+        self.cancelled_at = sqlx::query_scalar(query)
+            .bind(self.id)
+            .fetch_optional(executor)
+            .await?;
+
+        if self.cancelled_at.is_none() {
+            return Err(ScheduleError::Ended);
+        }
+
+        self.next_occurrence_at = None;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(raw: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(raw, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn schedule(starts_at: &str, recurrence: Recurrence) -> ScheduledTransaction {
+        ScheduledTransaction {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            account_id: Uuid::new_v4(),
+            amount: Money::from_wire(100).unwrap(),
+            transaction_type: TransactionType::OUTCOME,
+            description: None,
+            category_id: None,
+            tags: Vec::new(),
+            recurrence,
+            starts_at: at(starts_at),
+            occurrences: 0,
+            next_occurrence_at: None,
+            last_error: None,
+            cancelled_at: None,
+            created_at: String::new(),
+        }
+    }
+
+    fn every(frequency: Frequency, interval: i32) -> Recurrence {
+        Recurrence {
+            frequency,
+            interval,
+            ..Recurrence::once()
+        }
+    }
+
+    fn occurrences(schedule: &ScheduledTransaction, count: i32) -> Vec<Option<NaiveDateTime>> {
+        (0..count)
+            .map(|index| schedule.occurrence_at(index))
+            .collect()
+    }
+
+    #[test]
+    fn clamps_monthly_occurrences_to_short_months() {
+        for (starts_at, february) in [
+            ("2024-01-31 09:00", "2024-02-29 09:00"),
+            ("2023-01-31 09:00", "2023-02-28 09:00"),
+        ] {
+            let schedule = schedule(starts_at, every(Frequency::Monthly, 1));
+            let march = format!("{}-03-31 09:00", &starts_at[..4]);
+
+            assert_eq!(
+                occurrences(&schedule, 3),
+                [Some(at(starts_at)), Some(at(february)), Some(at(&march))]
+            );
+        }
+
+        let schedule = schedule(
+            "2024-01-10 09:00",
+            Recurrence {
+                day_of_month: Some(30),
+                ..every(Frequency::Monthly, 1)
+            },
+        );
+        assert_eq!(
+            occurrences(&schedule, 3),
+            [
+                Some(at("2024-01-30 09:00")),
+                Some(at("2024-02-29 09:00")),
+                Some(at("2024-03-30 09:00")),
+            ]
+        );
+    }
+
+    #[test]
+    fn starts_monthly_schedules_on_the_next_day_of_month() {
+        let on_the_15th = Recurrence {
+            day_of_month: Some(15),
+            ..every(Frequency::Monthly, 2)
+        };
+
+        let schedule_after = schedule("2024-01-20 09:00", on_the_15th.clone());
+        assert_eq!(
+            occurrences(&schedule_after, 2),
+            [Some(at("2024-02-15 09:00")), Some(at("2024-04-15 09:00"))]
+        );
+
+        let schedule_on = schedule("2024-01-15 09:00", on_the_15th);
+        assert_eq!(
+            occurrences(&schedule_on, 2),
+            [Some(at("2024-01-15 09:00")), Some(at("2024-03-15 09:00"))]
+        );
+    }
+
+    #[test]
+    fn steps_daily_and_weekly_schedules_by_their_interval() {
+        let daily = schedule("2024-02-27 09:00", every(Frequency::Daily, 3));
+        assert_eq!(
+            occurrences(&daily, 3),
+            [
+                Some(at("2024-02-27 09:00")),
+                Some(at("2024-03-01 09:00")),
+                Some(at("2024-03-04 09:00")),
+            ]
+        );
+
+        let weekly = schedule("2024-01-01 09:00", every(Frequency::Weekly, 2));
+        assert_eq!(
+            occurrences(&weekly, 3),
+            [
+                Some(at("2024-01-01 09:00")),
+                Some(at("2024-01-15 09:00")),
+                Some(at("2024-01-29 09:00")),
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_schedules_after_their_end_or_count() {
+        let once = schedule("2024-01-01 09:00", Recurrence::once());
+        assert_eq!(occurrences(&once, 2), [Some(at("2024-01-01 09:00")), None]);
+
+        let until = schedule(
+            "2024-01-01 09:00",
+            Recurrence {
+                ends_at: Some(at("2024-01-15 09:00")),
+                ..every(Frequency::Weekly, 1)
+            },
+        );
+        assert_eq!(
+            occurrences(&until, 4),
+            [
+                Some(at("2024-01-01 09:00")),
+                Some(at("2024-01-08 09:00")),
+                Some(at("2024-01-15 09:00")),
+                None,
+            ]
+        );
+
+        let counted = schedule(
+            "2024-01-31 09:00",
+            Recurrence {
+                max_occurrences: Some(2),
+                ..every(Frequency::Monthly, 1)
+            },
+        );
+        assert_eq!(
+            occurrences(&counted, 3),
+            [
+                Some(at("2024-01-31 09:00")),
+                Some(at("2024-02-29 09:00")),
+                None
+            ]
+        );
+        assert_eq!(counted.occurrence_at(-1), None);
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::{BTreeSet, HashMap};
+
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::models::transaction::Transaction;
+
+const MAX_TAG_LENGTH: usize = 64;
+
+/// Tags are compared case-insensitively, so they are stored lowercase.
+pub fn normalize(raw: &str) -> Result<String, String> {
+    let tag = raw.trim().to_lowercase();
+
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(format!(
+            "Tags must have between 1 and {} characters",
+            MAX_TAG_LENGTH
+        ));
+    }
+
+    Ok(tag)
+}
+
+/// Links the transactions to their tags, creating the tags the user doesn't
+/// have yet.
+pub async fn attach(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    transactions: &[Transaction],
+) -> Result<(), sqlx::Error> {
+    let names: Vec<&str> = transactions
+        .iter()
+        .flat_map(|transaction| transaction.tags.iter().map(String::as_str))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let upsert_query = r#"INSERT INTO tags (id, user_id, name)
+           SELECT gen_random_uuid(), $1, name FROM unnest($2::text[]) AS name
+           ON CONFLICT (user_id, name) DO NOTHING"#;
+
+    sqlx::query(upsert_query)
+        .bind(user_id)
+        .bind(&names)
+        .execute(&mut *conn)
+        .await?;
+
+    let select_query = r#"SELECT name, id FROM tags WHERE user_id = $1 AND name = ANY($2)"#;
+
+    let tag_ids: HashMap<String, Uuid> = sqlx::query_as(select_query)
+        .bind(user_id)
+        .bind(&names)
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .collect();
+
+    let (transaction_ids, link_tag_ids): (Vec<&str>, Vec<Uuid>) = transactions
+        .iter()
+        .flat_map(|transaction| {
+            transaction
+                .tags
+                .iter()
+                .filter_map(|tag| tag_ids.get(tag))
+                .map(|tag_id| (transaction.id.as_str(), *tag_id))
+        })
+        .unzip();
+
+    let link_query = r#"INSERT INTO transaction_tags (transaction_id, tag_id)
+           SELECT * FROM unnest($1::text[]::uuid[], $2::uuid[])
+           ON CONFLICT DO NOTHING"#;
+
+    sqlx::query(link_query)
+        .bind(&transaction_ids)
+        .bind(&link_tag_ids)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
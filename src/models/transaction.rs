@@ -1,9 +1,20 @@
 use std::fmt;
 
 use chrono::Utc;
+use sqlx::{
+    postgres::{PgRow, Postgres},
+    Executor, PgConnection, QueryBuilder, Row,
+};
 use uuid::Uuid;
 
-#[derive(Debug, PartialEq)]
+use crate::models::money::Money;
+use crate::pagination::PageToken;
+
+/// Rows per multi-row INSERT, well below the 65535 bind parameters limit.
+pub const INSERT_BATCH_SIZE: usize = 1000;
+
+#[derive(sqlx::Type, Debug, Clone, PartialEq)]
+#[sqlx(type_name = "transactiontype", rename_all = "UPPERCASE")]
 pub enum TransactionType {
     INCOME,
     OUTCOME,
@@ -17,6 +28,13 @@ impl TransactionType {
             _ => Err("Invalid transaction type".to_owned()),
         }
     }
+
+    pub fn to_proto(&self) -> i32 {
+        match self {
+            TransactionType::INCOME => 0,
+            TransactionType::OUTCOME => 1,
+        }
+    }
 }
 
 impl fmt::Display for TransactionType {
@@ -28,30 +46,612 @@ impl fmt::Display for TransactionType {
     }
 }
 
+#[derive(sqlx::Type, Debug, Clone, PartialEq)]
+#[sqlx(type_name = "transactionstatus", rename_all = "UPPERCASE")]
+pub enum TransactionStatus {
+    PENDING,
+    POSTED,
+    VOIDED,
+}
+
+impl TransactionStatus {
+    pub fn from_proto(value: &i32) -> Result<Self, String> {
+        match value {
+            0 => Ok(TransactionStatus::POSTED),
+            1 => Ok(TransactionStatus::PENDING),
+            2 => Ok(TransactionStatus::VOIDED),
+            _ => Err("Invalid transaction status".to_owned()),
+        }
+    }
+
+    pub fn to_proto(&self) -> i32 {
+        match self {
+            TransactionStatus::POSTED => 0,
+            TransactionStatus::PENDING => 1,
+            TransactionStatus::VOIDED => 2,
+        }
+    }
+}
+
+impl fmt::Display for TransactionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionStatus::PENDING => write!(f, "PENDING"),
+            TransactionStatus::POSTED => write!(f, "POSTED"),
+            TransactionStatus::VOIDED => write!(f, "VOIDED"),
+        }
+    }
+}
+
+/// Filters and keyset position of a `Transaction::list` page.
 #[derive(Debug)]
+pub struct TransactionFilter {
+    pub account_id: Uuid,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub transaction_type: Option<TransactionType>,
+    pub status: Option<TransactionStatus>,
+    pub min_amount: Option<Money>,
+    pub max_amount: Option<Money>,
+    pub after: Option<PageToken>,
+    pub limit: i64,
+}
+
+const SELECT_COLUMNS: &str = r#"SELECT id::text, amount, transaction_type, status, origin_account_id::text,
+              destination_account_id::text, transfer_id::text,
+              journal_entry_id::text, description, external_id,
+              category_id::text, reversal_of::text,
+              (SELECT reversal.id::text FROM transactions reversal
+               WHERE reversal.reversal_of = transactions.id) AS reversed_by,
+              version,
+              ARRAY(SELECT tags.name FROM transaction_tags
+                    JOIN tags ON tags.id = transaction_tags.tag_id
+                    WHERE transaction_tags.transaction_id = transactions.id
+                    ORDER BY tags.name) AS tags,
+              created_at::text"#;
+
+#[derive(Debug, Clone)]
 pub struct Transaction {
     pub id: String,
-    pub amount: f64,
+    pub amount: Money,
     pub transaction_type: TransactionType,
+    pub status: TransactionStatus,
     pub origin_account_id: String,
+    /// The other account of a transfer leg. On the OUTCOME leg it is the
+    /// destination of the transfer; on the INCOME leg, whose origin account
+    /// is the destination, it holds the source account instead.
+    pub destination_account_id: Option<String>,
+    pub transfer_id: Option<String>,
+    pub journal_entry_id: String,
     pub description: Option<String>,
+    /// Id of the bank statement line the transaction was imported from.
+    pub external_id: Option<String>,
+    pub category_id: Option<String>,
+    /// Normalized with `tag::normalize`. Stored apart, through `tag::attach`.
+    pub tags: Vec<String>,
+    /// Id of the transaction this one reverses.
+    pub reversal_of: Option<String>,
+    /// Id of the transaction reversing this one. Read only.
+    pub reversed_by: Option<String>,
+    /// Bumped by every amendment, see `TransactionVersion`.
+    pub version: i32,
     pub created_at: String,
 }
 
+/// The description and category a transaction had before an amendment.
+#[derive(Debug, Clone)]
+pub struct TransactionVersion {
+    pub transaction_id: String,
+    pub version: i32,
+    pub description: Option<String>,
+    pub category_id: Option<String>,
+    /// Reason given for the amendment that replaced this version.
+    pub amendment_reason: Option<String>,
+    pub superseded_at: String,
+}
+
 impl Transaction {
     pub fn new(
-        amount: f64,
+        amount: Money,
         transaction_type: TransactionType,
         origin_account_id: String,
         description: Option<String>,
     ) -> Self {
+        let id = Uuid::new_v4().to_string();
+
         Transaction {
-            id: Uuid::new_v4().to_string(),
+            journal_entry_id: id.clone(),
+            id,
             amount,
             description,
             origin_account_id,
+            destination_account_id: None,
+            transfer_id: None,
             transaction_type,
+            status: TransactionStatus::POSTED,
+            external_id: None,
+            category_id: None,
+            tags: Vec::new(),
+            reversal_of: None,
+            reversed_by: None,
+            version: 1,
             created_at: Utc::now().to_rfc3339(),
         }
     }
+
+    fn opposite_type(&self) -> TransactionType {
+        match self.transaction_type {
+            TransactionType::INCOME => TransactionType::OUTCOME,
+            TransactionType::OUTCOME => TransactionType::INCOME,
+        }
+    }
+
+    /// Builds the compensating transaction of one that isn't part of a
+    /// transfer: the same amount, category and tags the other way around.
+    pub fn reversal(&self, description: Option<String>) -> Transaction {
+        let mut reversal = Transaction::new(
+            self.amount,
+            self.opposite_type(),
+            self.origin_account_id.clone(),
+            description,
+        );
+        reversal.category_id = self.category_id.clone();
+        reversal.tags = self.tags.clone();
+        reversal.reversal_of = Some(self.id.clone());
+
+        reversal
+    }
+
+    /// Builds the compensating transfer of both legs of a transfer, moving
+    /// the money back from the destination to the source account.
+    pub fn transfer_reversal(
+        debit: &Transaction,
+        credit: &Transaction,
+        description: Option<String>,
+    ) -> (Transaction, Transaction) {
+        let (mut reversal_debit, mut reversal_credit) = Transaction::transfer(
+            debit.amount,
+            credit.origin_account_id.clone(),
+            debit.origin_account_id.clone(),
+            description,
+        );
+        reversal_debit.reversal_of = Some(credit.id.clone());
+        reversal_credit.reversal_of = Some(debit.id.clone());
+
+        (reversal_debit, reversal_credit)
+    }
+
+    /// Builds both legs of a transfer: an OUTCOME on the source account and
+    /// an INCOME on the destination, each pointing at the other account and
+    /// sharing the same transfer id, which is also their journal entry id.
+    pub fn transfer(
+        amount: Money,
+        from_account_id: String,
+        to_account_id: String,
+        description: Option<String>,
+    ) -> (Transaction, Transaction) {
+        let transfer_id = Uuid::new_v4().to_string();
+
+        let mut debit = Transaction::new(
+            amount,
+            TransactionType::OUTCOME,
+            from_account_id.clone(),
+            description.clone(),
+        );
+        debit.destination_account_id = Some(to_account_id.clone());
+        debit.transfer_id = Some(transfer_id.clone());
+        debit.journal_entry_id = transfer_id.clone();
+
+        let mut credit =
+            Transaction::new(amount, TransactionType::INCOME, to_account_id, description);
+        credit.destination_account_id = Some(from_account_id);
+        credit.transfer_id = Some(transfer_id.clone());
+        credit.journal_entry_id = transfer_id;
+
+        (debit, credit)
+    }
+
+    pub async fn insert(&self, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+        Transaction::insert_many(conn, std::slice::from_ref(self)).await
+    }
+
+    /// Inserts the transactions with multi-row INSERTs.
+    pub async fn insert_many(
+        conn: &mut PgConnection,
+        transactions: &[Transaction],
+    ) -> Result<(), sqlx::Error> {
+        for chunk in transactions.chunks(INSERT_BATCH_SIZE) {
+            let mut query = QueryBuilder::<Postgres>::new(
+                "INSERT INTO transactions (id, amount, transaction_type, status, origin_account_id, destination_account_id, transfer_id, journal_entry_id, description, external_id, category_id, reversal_of, created_at) ",
+            );
+
+            query.push_values(chunk, |mut row, transaction| {
+                row.push_bind(&transaction.id)
+                    .push_unseparated("::uuid")
+                    .push_bind(transaction.amount)
+                    .push_bind(transaction.transaction_type.to_string())
+                    .push_unseparated("::transactiontype")
+                    .push_bind(transaction.status.to_string())
+                    .push_unseparated("::transactionstatus")
+                    .push_bind(&transaction.origin_account_id)
+                    .push_unseparated("::uuid")
+                    .push_bind(&transaction.destination_account_id)
+                    .push_unseparated("::uuid")
+                    .push_bind(&transaction.transfer_id)
+                    .push_unseparated("::uuid")
+                    .push_bind(&transaction.journal_entry_id)
+                    .push_unseparated("::uuid")
+                    .push_bind(&transaction.description)
+                    .push_bind(&transaction.external_id)
+                    .push_bind(&transaction.category_id)
+                    .push_unseparated("::uuid")
+                    .push_bind(&transaction.reversal_of)
+                    .push_unseparated("::uuid")
+                    .push_bind(&transaction.created_at)
+                    .push_unseparated("::timestamp");
+            });
+
+            query.build().execute(&mut *conn).await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn from_pg_row(row: PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Transaction {
+            id: row.try_get("id")?,
+            amount: row.try_get("amount")?,
+            transaction_type: row.try_get("transaction_type")?,
+            status: row.try_get("status")?,
+            origin_account_id: row.try_get("origin_account_id")?,
+            destination_account_id: row.try_get("destination_account_id")?,
+            transfer_id: row.try_get("transfer_id")?,
+            journal_entry_id: row.try_get("journal_entry_id")?,
+            description: row.try_get("description")?,
+            external_id: row.try_get("external_id")?,
+            category_id: row.try_get("category_id")?,
+            tags: row.try_get("tags")?,
+            reversal_of: row.try_get("reversal_of")?,
+            reversed_by: row.try_get("reversed_by")?,
+            version: row.try_get("version")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+
+    pub async fn find<'c, E>(executor: E, id: Uuid) -> Result<Option<Transaction>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let mut query = QueryBuilder::<Postgres>::new(SELECT_COLUMNS);
+        query.push(" FROM transactions WHERE id = ");
+        query.push_bind(id);
+
+        query
+            .build()
+            .fetch_optional(executor)
+            .await?
+            .map(Transaction::from_pg_row)
+            .transpose()
+    }
+
+    /// Loads the transaction and locks its row until the surrounding DB
+    /// transaction ends.
+    pub async fn find_for_update(
+        conn: &mut PgConnection,
+        id: Uuid,
+    ) -> Result<Option<Transaction>, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(SELECT_COLUMNS);
+        query.push(" FROM transactions WHERE id = ");
+        query.push_bind(id);
+        query.push(" FOR UPDATE");
+
+        query
+            .build()
+            .fetch_optional(conn)
+            .await?
+            .map(Transaction::from_pg_row)
+            .transpose()
+    }
+
+    /// Both legs of a transfer, the OUTCOME first.
+    pub async fn find_transfer<'c, E>(
+        executor: E,
+        transfer_id: &str,
+    ) -> Result<Vec<Transaction>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let mut query = QueryBuilder::<Postgres>::new(SELECT_COLUMNS);
+        query.push(" FROM transactions WHERE transfer_id = ");
+        query.push_bind(transfer_id).push("::uuid");
+        query.push(" ORDER BY transaction_type DESC, id");
+
+        query
+            .build()
+            .fetch_all(executor)
+            .await?
+            .into_iter()
+            .map(Transaction::from_pg_row)
+            .collect()
+    }
+
+    /// Moves a pending transaction to its final status. The row must be
+    /// locked with `find_for_update`.
+    pub async fn set_status(
+        &mut self,
+        conn: &mut PgConnection,
+        status: TransactionStatus,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE transactions SET status = $2::transactionstatus WHERE id = $1::uuid")
+            .bind(&self.id)
+            .bind(status.to_string())
+            .execute(conn)
+            .await?;
+        self.status = status;
+
+        Ok(())
+    }
+
+    /// Stores a new description and category, keeping the previous ones in
+    /// the history of the transaction. The row must be locked with
+    /// `find_for_update`.
+    pub async fn amend(
+        &mut self,
+        conn: &mut PgConnection,
+        description: Option<String>,
+        category_id: Option<String>,
+        reason: Option<String>,
+    ) -> Result<(), sqlx::Error> {
+        let history_query = r#"INSERT INTO transaction_versions (transaction_id, version, description,
+                   category_id, amendment_reason, superseded_at)
+               VALUES ($1::uuid, $2, $3, $4::uuid, $5, CURRENT_TIMESTAMP)"#;
+
+        sqlx::query(history_query)
+            .bind(&self.id)
+            .bind(self.version)
+            .bind(&self.description)
+            .bind(&self.category_id)
+            .bind(reason)
+            .execute(&mut *conn)
+            .await?;
+
+        let update_query = r#"UPDATE transactions
+               SET description = $2, category_id = $3::uuid, version = version + 1
+               WHERE id = $1::uuid
+               RETURNING version"#;
+
+        self.version = sqlx::query_scalar(update_query)
+            .bind(&self.id)
+            .bind(&description)
+            .bind(&category_id)
+            .fetch_one(&mut *conn)
+            .await?;
+        self.description = description;
+        self.category_id = category_id;
+
+        Ok(())
+    }
+
+    /// Returns which of the statement line ids were already imported into
+    /// the account.
+    pub async fn find_external_ids<'c, E>(
+        executor: E,
+        account_id: Uuid,
+        external_ids: &[String],
+    ) -> Result<Vec<String>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT external_id FROM transactions
+               WHERE origin_account_id = $1 AND external_id = ANY($2)"#;
+
+        sqlx::query_scalar(query)
+            .bind(account_id)
+            .bind(external_ids)
+            .fetch_all(executor)
+            .await
+    }
+
+    /// Lists the transactions of an account ordered by `(created_at, id)`,
+    /// starting after the page token when one is given.
+    pub async fn list<'c, E>(
+        executor: E,
+        filter: &TransactionFilter,
+    ) -> Result<Vec<Transaction>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let mut query = QueryBuilder::<Postgres>::new(SELECT_COLUMNS);
+        query.push(" FROM transactions WHERE origin_account_id = ");
+        query.push_bind(filter.account_id);
+
+        if let Some(from) = &filter.from {
+            query.push(" AND created_at >= ");
+            query.push_bind(from).push("::timestamp");
+        }
+
+        if let Some(to) = &filter.to {
+            query.push(" AND created_at < ");
+            query.push_bind(to).push("::timestamp");
+        }
+
+        if let Some(transaction_type) = &filter.transaction_type {
+            query.push(" AND transaction_type = ");
+            query
+                .push_bind(transaction_type.to_string())
+                .push("::transactiontype");
+        }
+
+        if let Some(status) = &filter.status {
+            query.push(" AND status = ");
+            query.push_bind(status.to_string()).push("::transactionstatus");
+        }
+
+        if let Some(min_amount) = filter.min_amount {
+            query.push(" AND amount >= ");
+            query.push_bind(min_amount);
+        }
+
+        if let Some(max_amount) = filter.max_amount {
+            query.push(" AND amount <= ");
+            query.push_bind(max_amount);
+        }
+
+        if let Some(after) = &filter.after {
+            query.push(" AND (created_at, id) > (");
+            query.push_bind(&after.created_at).push("::timestamp, ");
+            query.push_bind(&after.id).push("::uuid)");
+        }
+
+        query.push(" ORDER BY created_at, id LIMIT ");
+        query.push_bind(filter.limit);
+
+        query
+            .build()
+            .fetch_all(executor)
+            .await?
+            .into_iter()
+            .map(Transaction::from_pg_row)
+            .collect()
+    }
+
+    /// Lists the transactions of every account of the user ordered by
+    /// `(created_at, id)`, starting after the page token when one is given.
+    pub async fn list_for_user<'c, E>(
+        executor: E,
+        user_id: Uuid,
+        after: Option<&PageToken>,
+        limit: i64,
+    ) -> Result<Vec<Transaction>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let mut query = QueryBuilder::<Postgres>::new(SELECT_COLUMNS);
+        query.push(
+            " FROM transactions WHERE origin_account_id IN (SELECT id FROM bank_accounts WHERE user_id = ",
+        );
+        query.push_bind(user_id).push(")");
+
+        if let Some(after) = after {
+            query.push(" AND (created_at, id) > (");
+            query.push_bind(&after.created_at).push("::timestamp, ");
+            query.push_bind(&after.id).push("::uuid)");
+        }
+
+        query.push(" ORDER BY created_at, id LIMIT ");
+        query.push_bind(limit);
+
+        query
+            .build()
+            .fetch_all(executor)
+            .await?
+            .into_iter()
+            .map(Transaction::from_pg_row)
+            .collect()
+    }
+
+    /// Lists the most recent transactions of every account of the user,
+    /// newest first.
+    pub async fn latest_for_user<'c, E>(
+        executor: E,
+        user_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<Transaction>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let mut query = QueryBuilder::<Postgres>::new(SELECT_COLUMNS);
+        query.push(
+            " FROM transactions WHERE origin_account_id IN (SELECT id FROM bank_accounts WHERE user_id = ",
+        );
+        query.push_bind(user_id);
+        query.push(") ORDER BY created_at DESC, id DESC LIMIT ");
+        query.push_bind(limit);
+
+        query
+            .build()
+            .fetch_all(executor)
+            .await?
+            .into_iter()
+            .map(Transaction::from_pg_row)
+            .collect()
+    }
+
+    /// Stores the category of the transactions, keeping the previous one in
+    /// their history with the given reason. Their tags are stored with
+    /// `tag::attach`.
+    pub async fn update_categories(
+        conn: &mut PgConnection,
+        transactions: &[Transaction],
+        reason: &str,
+    ) -> Result<(), sqlx::Error> {
+        let (ids, category_ids): (Vec<&str>, Vec<Option<&str>>) = transactions
+            .iter()
+            .map(|transaction| (transaction.id.as_str(), transaction.category_id.as_deref()))
+            .unzip();
+
+        let query = r#"WITH changes AS (
+                   SELECT changes.id, changes.category_id
+                   FROM unnest($1::text[]::uuid[], $2::text[]::uuid[]) AS changes(id, category_id)
+                   JOIN transactions ON transactions.id = changes.id
+                   WHERE transactions.category_id IS DISTINCT FROM changes.category_id
+               ),
+               history AS (
+                   INSERT INTO transaction_versions (transaction_id, version, description,
+                       category_id, amendment_reason, superseded_at)
+                   SELECT transactions.id, transactions.version, transactions.description,
+                          transactions.category_id, $3, CURRENT_TIMESTAMP
+                   FROM transactions
+                   JOIN changes ON changes.id = transactions.id
+               )
+               UPDATE transactions
+               SET category_id = changes.category_id, version = transactions.version + 1
+               FROM changes
+               WHERE transactions.id = changes.id"#;
+
+        sqlx::query(query)
+            .bind(&ids)
+            .bind(&category_ids)
+            .bind(reason)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl TransactionVersion {
+    fn from_pg_row(row: PgRow) -> Result<Self, sqlx::Error> {
+        Ok(TransactionVersion {
+            transaction_id: row.try_get("transaction_id")?,
+            version: row.try_get("version")?,
+            description: row.try_get("description")?,
+            category_id: row.try_get("category_id")?,
+            amendment_reason: row.try_get("amendment_reason")?,
+            superseded_at: row.try_get("superseded_at")?,
+        })
+    }
+
+    /// The previous versions of the transaction, oldest first.
+    pub async fn list<'c, E>(
+        executor: E,
+        transaction_id: Uuid,
+    ) -> Result<Vec<TransactionVersion>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT transaction_id::text, version, description, category_id::text,
+                      amendment_reason, superseded_at::text
+               FROM transaction_versions
+               WHERE transaction_id = $1
+               ORDER BY version"#;
+
+        sqlx::query(query)
+            .bind(transaction_id)
+            .fetch_all(executor)
+            .await?
+            .into_iter()
+            .map(TransactionVersion::from_pg_row)
+            .collect()
+    }
 }
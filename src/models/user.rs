@@ -1,9 +1,16 @@
+use std::fmt;
+
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    postgres::{PgRow, Postgres},
+    Executor, Row,
+};
 use thiserror::Error;
 use uuid::Uuid;
 
 use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
 
@@ -17,6 +24,16 @@ impl Password {
         Password { value: raw }
     }
 
+    /// A password chosen by a user, before it is hashed. Blank ones are
+    /// refused.
+    pub fn from_raw(raw: String) -> Result<Password, UserError> {
+        if raw.trim().is_empty() {
+            return Err(UserError::InvalidPassword);
+        }
+
+        Ok(Password::new(raw))
+    }
+
     pub fn get_hashed_value(&mut self) -> Result<(), UserError> {
         let salt = SaltString::generate(&mut OsRng);
 
@@ -31,6 +48,14 @@ impl Password {
             Err(_hash_error) => Err(UserError::PasswordHash),
         }
     }
+
+    pub fn verify(&self, raw: &str) -> Result<(), UserError> {
+        let hashed = PasswordHash::new(&self.value).map_err(|_err| UserError::PasswordHash)?;
+
+        Argon2::default()
+            .verify_password(raw.as_bytes(), &hashed)
+            .map_err(|_err| UserError::InvalidCredentials)
+    }
 }
 
 #[derive(Error, Debug)]
@@ -40,6 +65,33 @@ pub enum UserError {
 
     #[error("Internal server error")]
     PasswordHash,
+
+    #[error("Invalid email or password")]
+    InvalidCredentials,
+
+    #[error("Current password is incorrect")]
+    IncorrectPassword,
+
+    #[error("The password can't be blank")]
+    InvalidPassword,
+}
+
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "userrole", rename_all = "UPPERCASE")]
+pub enum Role {
+    USER,
+    ADMIN,
+    AUDITOR,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::USER => write!(f, "USER"),
+            Role::ADMIN => write!(f, "ADMIN"),
+            Role::AUDITOR => write!(f, "AUDITOR"),
+        }
+    }
 }
 
 #[derive(Debug, sqlx::FromRow)]
@@ -48,6 +100,8 @@ pub struct User {
     pub name: String,
     pub email: String,
     pub password: Password,
+    pub role: Role,
+    pub token_version: i32,
     pub created_at: String,
 }
 
@@ -57,7 +111,9 @@ impl User {
             id: Uuid::new_v4().to_string(),
             name,
             email,
-            password: Password::new(raw_password),
+            password: Password::from_raw(raw_password)?,
+            role: Role::USER,
+            token_version: 0,
             created_at: Utc::now().to_rfc3339(),
         };
 
@@ -71,6 +127,8 @@ impl User {
         name: String,
         email: String,
         password: String,
+        role: Role,
+        token_version: i32,
         created_at: String,
     ) -> Result<User, String> {
         let user_id = Uuid::try_parse(id.as_str())
@@ -82,9 +140,115 @@ impl User {
             name,
             email,
             password: Password::new(password),
+            role,
+            token_version,
             created_at,
         };
 
         Ok(user)
     }
+
+    fn from_pg_row(row: PgRow) -> Result<User, sqlx::Error> {
+        User::from_db(
+            row.try_get("id")?,
+            row.try_get("name")?,
+            row.try_get("email")?,
+            row.try_get("password")?,
+            row.try_get("role")?,
+            row.try_get("token_version")?,
+            row.try_get("created_at")?,
+        )
+        .map_err(|err| sqlx::Error::Decode(err.into()))
+    }
+
+    pub async fn find_by_id<'c, E>(executor: E, id: Uuid) -> Result<Option<User>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT id::text, name, email, password, role, token_version, created_at::text
+               FROM users
+               WHERE id = $1"#;
+
+        sqlx::query(query)
+            .bind(id)
+            .fetch_optional(executor)
+            .await?
+            .map(User::from_pg_row)
+            .transpose()
+    }
+
+    pub async fn find_by_email<'c, E>(executor: E, email: &str) -> Result<Option<User>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"SELECT id::text, name, email, password, role, token_version, created_at::text
+               FROM users
+               WHERE email = $1"#;
+
+        sqlx::query(query)
+            .bind(email)
+            .fetch_optional(executor)
+            .await?
+            .map(User::from_pg_row)
+            .transpose()
+    }
+
+    pub async fn update_profile<'c, E>(
+        executor: E,
+        id: Uuid,
+        name: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<(), sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"UPDATE users
+               SET name = COALESCE($2, name), email = COALESCE($3, email)
+               WHERE id = $1"#;
+
+        sqlx::query(query)
+            .bind(id)
+            .bind(name)
+            .bind(email)
+            .execute(executor)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Stores the already hashed password and bumps the token version so
+    /// every access token issued before the change stops working. Returns
+    /// the new token version.
+    pub async fn update_password<'c, E>(
+        executor: E,
+        id: &str,
+        password: &Password,
+    ) -> Result<i32, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        let query = r#"UPDATE users
+               SET password = $2, token_version = token_version + 1
+               WHERE id = $1::uuid
+               RETURNING token_version"#;
+
+        sqlx::query_scalar(query)
+            .bind(id)
+            .bind(&password.value)
+            .fetch_one(executor)
+            .await
+    }
+
+    pub async fn current_token_version<'c, E>(
+        executor: E,
+        id: &Uuid,
+    ) -> Result<Option<i32>, sqlx::Error>
+    where
+        E: Executor<'c, Database = Postgres>,
+    {
+        sqlx::query_scalar("SELECT token_version FROM users WHERE id = $1")
+            .bind(id)
+            .fetch_optional(executor)
+            .await
+    }
 }
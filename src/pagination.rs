@@ -0,0 +1,147 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use thiserror::Error;
+use uuid::Uuid;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// Format of `created_at::text` for TIMESTAMP columns.
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+#[derive(Error, Debug)]
+pub enum PaginationError {
+    #[error("Invalid page token")]
+    InvalidPageToken,
+    #[error("Invalid timestamp, expected RFC 3339")]
+    InvalidTimestamp,
+}
+
+pub fn page_size(requested: i32) -> i64 {
+    if requested <= 0 {
+        return DEFAULT_PAGE_SIZE;
+    }
+
+    i64::from(requested).min(MAX_PAGE_SIZE)
+}
+
+/// Converts an RFC 3339 filter into the UTC timestamp text the TIMESTAMP
+/// columns are compared against.
+pub fn parse_timestamp(raw: &str) -> Result<String, PaginationError> {
+    DateTime::parse_from_rfc3339(raw)
+        .map(|timestamp| {
+            timestamp
+                .with_timezone(&Utc)
+                .naive_utc()
+                .format(TIMESTAMP_FORMAT)
+                .to_string()
+        })
+        .map_err(|_err| PaginationError::InvalidTimestamp)
+}
+
+/// Formats the text of a TIMESTAMP column, which holds UTC, as RFC 3339 like
+/// the timestamps the filters take. Anything else, such as the RFC 3339
+/// `created_at` of a transaction that was just built, is returned as is.
+pub fn format_timestamp(raw: &str) -> String {
+    NaiveDateTime::parse_from_str(raw, TIMESTAMP_FORMAT)
+        .map(|timestamp| timestamp.and_utc().to_rfc3339())
+        .unwrap_or_else(|_err| raw.to_owned())
+}
+
+/// Opaque keyset cursor pointing at the last row of a page. Rows are ordered
+/// by `(created_at, id)` so the next page starts strictly after it.
+#[derive(Debug)]
+pub struct PageToken {
+    pub created_at: String,
+    pub id: String,
+}
+
+impl PageToken {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}|{}", self.created_at, self.id))
+    }
+
+    pub fn decode(raw: &str) -> Result<Option<PageToken>, PaginationError> {
+        if raw.is_empty() {
+            return Ok(None);
+        }
+
+        let decoded = URL_SAFE_NO_PAD
+            .decode(raw)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or(PaginationError::InvalidPageToken)?;
+
+        let (created_at, id) = decoded
+            .split_once('|')
+            .ok_or(PaginationError::InvalidPageToken)?;
+
+        NaiveDateTime::parse_from_str(created_at, TIMESTAMP_FORMAT)
+            .map_err(|_err| PaginationError::InvalidPageToken)?;
+        Uuid::try_parse(id).map_err(|_err| PaginationError::InvalidPageToken)?;
+
+        Ok(Some(PageToken {
+            created_at: created_at.to_owned(),
+            id: id.to_owned(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_page_tokens() {
+        let token = PageToken {
+            created_at: "2024-03-05 10:20:30.123456".to_owned(),
+            id: "0b7c6a34-8d1e-4f6a-9c1e-2a3b4c5d6e7f".to_owned(),
+        };
+
+        let decoded = PageToken::decode(&token.encode()).unwrap().unwrap();
+
+        assert_eq!(decoded.created_at, token.created_at);
+        assert_eq!(decoded.id, token.id);
+        assert!(PageToken::decode("").unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_page_tokens() {
+        let encode = |raw: &str| URL_SAFE_NO_PAD.encode(raw);
+
+        for raw in [
+            "not base64!".to_owned(),
+            URL_SAFE_NO_PAD.encode([0xff, 0xfe]),
+            encode("2024-03-05 10:20:30"),
+            encode("yesterday|0b7c6a34-8d1e-4f6a-9c1e-2a3b4c5d6e7f"),
+            encode("2024-03-05 10:20:30|42"),
+        ] {
+            assert!(
+                matches!(
+                    PageToken::decode(&raw),
+                    Err(PaginationError::InvalidPageToken)
+                ),
+                "{}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn converts_timestamps_between_rfc_3339_and_column_text() {
+        assert_eq!(
+            parse_timestamp("2024-03-05T12:20:30.5+02:00").unwrap(),
+            "2024-03-05 10:20:30.500"
+        );
+        assert!(parse_timestamp("2024-03-05 10:20:30").is_err());
+
+        assert_eq!(
+            format_timestamp("2024-03-05 10:20:30.123456"),
+            "2024-03-05T10:20:30.123456+00:00"
+        );
+        assert_eq!(
+            format_timestamp("2024-03-05T10:20:30+00:00"),
+            "2024-03-05T10:20:30+00:00"
+        );
+    }
+}
@@ -0,0 +1,592 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use thiserror::Error;
+use tonic::Status;
+use uuid::Uuid;
+
+use crate::auth::AccessError;
+use crate::events::{AccountEvent, AccountEvents};
+use crate::models::bank_account::{BankAccount, BankAccountError};
+use crate::models::budget::{self, BudgetAlert};
+use crate::models::category::{Category, CategoryError};
+use crate::models::currency::{Currency, CurrencyError};
+use crate::models::ledger::{self, JournalEntry, LedgerError};
+use crate::models::money::Money;
+use crate::models::rule::{RuleError, RuleSet};
+use crate::models::tag;
+use crate::models::transaction::{Transaction, TransactionStatus, TransactionType};
+use crate::proto;
+use crate::tracing::error;
+
+#[derive(Error, Debug)]
+pub enum PostingError {
+    #[error("Bank account not found")]
+    AccountNotFound,
+    #[error("Transaction not found")]
+    TransactionNotFound,
+    #[error("The transaction has already been reversed")]
+    AlreadyReversed,
+    #[error("A reversal can't be reversed")]
+    IsReversal,
+    #[error("Only posted transactions can be reversed")]
+    NotPosted,
+    #[error("The transaction is not pending")]
+    NotPending,
+    #[error(transparent)]
+    Access(#[from] AccessError),
+    #[error(transparent)]
+    Category(#[from] CategoryError),
+    #[error(transparent)]
+    Currency(#[from] CurrencyError),
+    #[error(transparent)]
+    Rejected(#[from] BankAccountError),
+    #[error(transparent)]
+    Rules(#[from] RuleError),
+    #[error(transparent)]
+    Ledger(#[from] LedgerError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl PostingError {
+    /// Whether the transaction was refused, as opposed to failing.
+    pub fn is_rejection(&self) -> bool {
+        matches!(
+            self,
+            PostingError::AccountNotFound
+                | PostingError::Access(_)
+                | PostingError::Category(CategoryError::NotFound)
+                | PostingError::Currency(CurrencyError::Mismatch { .. })
+                | PostingError::Rejected(_)
+        )
+    }
+}
+
+impl From<PostingError> for Status {
+    fn from(err: PostingError) -> Self {
+        match err {
+            PostingError::AccountNotFound
+            | PostingError::Category(CategoryError::NotFound)
+            | PostingError::Rejected(_) => Status::invalid_argument(err.to_string()),
+            PostingError::TransactionNotFound => Status::not_found(err.to_string()),
+            PostingError::AlreadyReversed
+            | PostingError::IsReversal
+            | PostingError::NotPosted
+            | PostingError::NotPending => Status::failed_precondition(err.to_string()),
+            PostingError::Access(err) => err.into(),
+            PostingError::Currency(err) => err.into(),
+            PostingError::Rules(err) => err.into(),
+            err => {
+                error!("Error while posting transaction: {:?}", err);
+                Status::internal("Internal server error")
+            }
+        }
+    }
+}
+
+/// A validated `ExecuteTransactionRequest`. Whether the category can be used
+/// by the caller is checked against the database afterwards.
+pub struct TransactionInput {
+    pub transaction_type: TransactionType,
+    pub amount: Money,
+    pub category_id: Option<Uuid>,
+    pub tags: Vec<String>,
+    pub pending: bool,
+    /// Unset when the request left it to the account currency.
+    pub currency: Option<String>,
+    /// When an imported transaction happened; it becomes its `created_at`.
+    pub occurred_at: Option<DateTime<Utc>>,
+}
+
+/// Validation shared by `ExecuteTransaction`, every line of
+/// `ImportTransactions` and `ScheduleTransaction`.
+pub fn validate_transaction_input(
+    input: &proto::ExecuteTransactionRequest,
+) -> Result<TransactionInput, String> {
+    let transaction_type = TransactionType::from_proto(&input.transaction_type)?;
+
+    let amount = Money::from_wire(input.amount_minor).map_err(|err| err.to_string())?;
+
+    if amount.is_zero() {
+        return Err("The amount must be greater than zero".to_owned());
+    }
+
+    let category_id = input
+        .category_id
+        .as_deref()
+        .map(Uuid::try_parse)
+        .transpose()
+        .map_err(|_err| CategoryError::NotFound.to_string())?;
+
+    let mut tags = input
+        .tags
+        .iter()
+        .map(|raw| tag::normalize(raw))
+        .collect::<Result<Vec<_>, _>>()?;
+    tags.sort();
+    tags.dedup();
+
+    let currency = Some(input.currency.trim())
+        .filter(|code| !code.is_empty())
+        .map(|code| Currency::normalize_code(code, ""))
+        .transpose()
+        .map_err(|err| err.to_string())?;
+
+    let occurred_at = input
+        .occurred_at
+        .as_deref()
+        .map(DateTime::parse_from_rfc3339)
+        .transpose()
+        .map_err(|_err| "Invalid occurred_at, expected RFC 3339".to_owned())?
+        .map(|occurred_at| occurred_at.with_timezone(&Utc));
+
+    if occurred_at.is_some_and(|occurred_at| occurred_at > Utc::now()) {
+        return Err("The transaction can't have occurred in the future".to_owned());
+    }
+
+    Ok(TransactionInput {
+        transaction_type,
+        amount,
+        category_id,
+        tags,
+        pending: input.pending,
+        currency,
+        occurred_at,
+    })
+}
+
+impl TransactionInput {
+    /// Amounts are in minor units of the account currency, so one given in
+    /// another currency can't be recorded without a conversion.
+    pub fn ensure_currency(&self, account: &BankAccount) -> Result<(), CurrencyError> {
+        match &self.currency {
+            Some(currency) => account.currency.ensure_matches(currency),
+            None => Ok(()),
+        }
+    }
+
+    pub fn into_transaction(self, account_id: String, description: Option<String>) -> Transaction {
+        let mut transaction =
+            Transaction::new(self.amount, self.transaction_type, account_id, description);
+        transaction.category_id = self.category_id.map(|id| id.to_string());
+        transaction.tags = self.tags;
+
+        if let Some(occurred_at) = self.occurred_at {
+            transaction.created_at = occurred_at.to_rfc3339();
+        }
+
+        if self.pending {
+            transaction.status = TransactionStatus::PENDING;
+        }
+
+        transaction
+    }
+}
+
+/// A transaction written by `post_transaction`, with the events to publish
+/// once its DB transaction commits.
+pub struct PostedTransaction {
+    pub transaction: Transaction,
+    pub balance: Money,
+    pub available_balance: Money,
+    pub budget_alerts: Vec<BudgetAlert>,
+}
+
+impl PostedTransaction {
+    /// Must only be called once the DB transaction has been committed.
+    pub fn publish(self, events: &AccountEvents) {
+        let account_id = self.transaction.origin_account_id.clone();
+        let transaction_id = self.transaction.id.clone();
+
+        if self.transaction.status == TransactionStatus::VOIDED {
+            events.publish(AccountEvent::TransactionVoided {
+                transaction: self.transaction,
+                available_balance: self.available_balance,
+            });
+        } else {
+            events.publish(AccountEvent::TransactionPosted {
+                transaction: self.transaction,
+                balance: self.balance,
+                available_balance: self.available_balance,
+            });
+        }
+
+        for alert in self.budget_alerts {
+            events.publish(AccountEvent::BudgetAlert {
+                account_id: account_id.clone(),
+                transaction_id: transaction_id.clone(),
+                alert,
+            });
+        }
+    }
+}
+
+/// Posts a transaction on an account of the user: runs the categorization
+/// rules, writes the ledger, the transaction and the new balance, and checks
+/// the budgets. This is the path of `ExecuteTransaction` and of the
+/// scheduled occurrences; the caller commits `conn`.
+pub async fn post_transaction(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    account_id: Uuid,
+    input: TransactionInput,
+    description: Option<String>,
+) -> Result<PostedTransaction, PostingError> {
+    // The account row stays locked until commit, so concurrent transactions
+    // on it are applied one after the other.
+    let mut account = BankAccount::find_for_update(&mut *conn, account_id)
+        .await?
+        .ok_or(PostingError::AccountNotFound)?;
+
+    if account.user_id != user_id {
+        return Err(AccessError::NotOwner.into());
+    }
+
+    input.ensure_currency(&account)?;
+
+    if let Some(category_id) = input.category_id {
+        Category::find_visible(&mut *conn, user_id, category_id)
+            .await?
+            .ok_or(CategoryError::NotFound)?;
+    }
+
+    let rules = RuleSet::load(&mut *conn, user_id).await?;
+
+    let mut transaction = input.into_transaction(account.id.to_string(), description);
+    rules.apply(&mut transaction, false);
+
+    account.update_balance(&transaction)?;
+
+    let journal_entry = JournalEntry::for_transaction(&transaction);
+
+    if transaction.status == TransactionStatus::PENDING {
+        journal_entry.insert_pending(&mut *conn).await?;
+    } else {
+        journal_entry.insert(&mut *conn).await?;
+    }
+
+    transaction.insert(&mut *conn).await?;
+
+    tag::attach(&mut *conn, user_id, std::slice::from_ref(&transaction)).await?;
+
+    let budget_alerts =
+        budget::check_alerts(&mut *conn, user_id, &account.currency.code, &transaction).await?;
+
+    account.save_balance(&mut *conn).await?;
+
+    ledger::verify_balance(&mut *conn, &account).await?;
+
+    Ok(PostedTransaction {
+        transaction,
+        balance: account.balance,
+        available_balance: account.available_balance,
+        budget_alerts,
+    })
+}
+
+/// Reverses a transaction of the user with a compensating one, restoring the
+/// balance it changed. Reversing either leg of a transfer reverses the whole
+/// transfer. The caller commits `conn`.
+pub async fn reverse_transaction(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    transaction_id: Uuid,
+    description: Option<String>,
+) -> Result<Vec<PostedTransaction>, PostingError> {
+    let original = Transaction::find(&mut *conn, transaction_id)
+        .await?
+        .ok_or(PostingError::TransactionNotFound)?;
+
+    let mut account_ids = std::iter::once(&original.origin_account_id)
+        .chain(&original.destination_account_id)
+        .map(|account_id| Uuid::try_parse(account_id))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_err| PostingError::AccountNotFound)?;
+    account_ids.sort();
+
+    // Same lock order as `TransferFunds`, and the legs are read again once
+    // the accounts are locked so a concurrent reversal is seen.
+    let mut accounts = Vec::with_capacity(account_ids.len());
+
+    for account_id in account_ids {
+        let account = BankAccount::find_for_update(&mut *conn, account_id)
+            .await?
+            .ok_or(PostingError::AccountNotFound)?;
+
+        if account.user_id != user_id {
+            return Err(AccessError::NotOwner.into());
+        }
+
+        accounts.push(account);
+    }
+
+    let legs = match &original.transfer_id {
+        Some(transfer_id) => Transaction::find_transfer(&mut *conn, transfer_id).await?,
+        None => Transaction::find(&mut *conn, transaction_id)
+            .await?
+            .into_iter()
+            .collect(),
+    };
+
+    for leg in &legs {
+        if leg.status != TransactionStatus::POSTED {
+            return Err(PostingError::NotPosted);
+        }
+
+        if leg.reversal_of.is_some() {
+            return Err(PostingError::IsReversal);
+        }
+
+        if leg.reversed_by.is_some() {
+            return Err(PostingError::AlreadyReversed);
+        }
+    }
+
+    let (reversals, journal_entry) = match legs.as_slice() {
+        [transaction] => {
+            let reversal = transaction.reversal(description);
+            let journal_entry = JournalEntry::for_transaction(&reversal);
+
+            (vec![reversal], journal_entry)
+        }
+        [debit, credit] => {
+            let (reversal_debit, reversal_credit) =
+                Transaction::transfer_reversal(debit, credit, description);
+            let journal_entry = JournalEntry::for_transfer(&reversal_debit, &reversal_credit);
+
+            (vec![reversal_debit, reversal_credit], journal_entry)
+        }
+        _ => return Err(PostingError::TransactionNotFound),
+    };
+
+    let mut balances = Vec::with_capacity(reversals.len());
+
+    for reversal in &reversals {
+        let account = accounts
+            .iter_mut()
+            .find(|account| account.id.to_string() == reversal.origin_account_id)
+            .ok_or(PostingError::AccountNotFound)?;
+
+        account.update_balance(reversal)?;
+        balances.push((account.balance, account.available_balance));
+    }
+
+    journal_entry.insert(&mut *conn).await?;
+
+    Transaction::insert_many(&mut *conn, &reversals).await?;
+
+    tag::attach(&mut *conn, user_id, &reversals).await?;
+
+    for account in &accounts {
+        account.save_balance(&mut *conn).await?;
+
+        ledger::verify_balance(&mut *conn, account).await?;
+    }
+
+    Ok(reversals
+        .into_iter()
+        .zip(balances)
+        .map(
+            |(transaction, (balance, available_balance))| PostedTransaction {
+                transaction,
+                balance,
+                available_balance,
+                budget_alerts: Vec::new(),
+            },
+        )
+        .collect())
+}
+
+/// Posts or voids a pending transaction of the user. Posting writes its
+/// ledger postings and moves the balance; voiding releases the hold on the
+/// available balance. The caller commits `conn`.
+pub async fn resolve_pending(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    transaction_id: Uuid,
+    status: TransactionStatus,
+) -> Result<PostedTransaction, PostingError> {
+    let pending = Transaction::find(&mut *conn, transaction_id)
+        .await?
+        .ok_or(PostingError::TransactionNotFound)?;
+
+    let account_id = Uuid::try_parse(&pending.origin_account_id)
+        .map_err(|_err| PostingError::AccountNotFound)?;
+
+    let mut account = BankAccount::find_for_update(&mut *conn, account_id)
+        .await?
+        .ok_or(PostingError::AccountNotFound)?;
+
+    if account.user_id != user_id {
+        return Err(AccessError::NotOwner.into());
+    }
+
+    let mut transaction = Transaction::find_for_update(&mut *conn, transaction_id)
+        .await?
+        .ok_or(PostingError::TransactionNotFound)?;
+
+    if transaction.status != TransactionStatus::PENDING {
+        return Err(PostingError::NotPending);
+    }
+
+    match status {
+        TransactionStatus::POSTED => {
+            account.settle(&transaction)?;
+
+            JournalEntry::for_transaction(&transaction)
+                .insert_postings(&mut *conn)
+                .await?;
+        }
+        TransactionStatus::VOIDED => account.release(&transaction)?,
+        TransactionStatus::PENDING => return Err(PostingError::NotPending),
+    }
+
+    transaction.set_status(&mut *conn, status).await?;
+
+    account.save_balance(&mut *conn).await?;
+
+    ledger::verify_balance(&mut *conn, &account).await?;
+
+    Ok(PostedTransaction {
+        transaction,
+        balance: account.balance,
+        available_balance: account.available_balance,
+        budget_alerts: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    const INCOME: i64 = 100;
+    const OUTCOME: i64 = 250;
+
+    fn input(transaction_type: TransactionType, amount: i64) -> TransactionInput {
+        TransactionInput {
+            transaction_type,
+            amount: Money::from_wire(amount).unwrap(),
+            category_id: None,
+            tags: Vec::new(),
+            pending: false,
+            currency: None,
+            occurred_at: None,
+        }
+    }
+
+    async fn post(
+        pool: &PgPool,
+        user_id: Uuid,
+        account_id: Uuid,
+        input: TransactionInput,
+    ) -> Result<PostedTransaction, PostingError> {
+        let mut txn = pool.begin().await?;
+        let posted = post_transaction(&mut txn, user_id, account_id, input, None).await?;
+        txn.commit().await?;
+
+        Ok(posted)
+    }
+
+    /// Hundreds of concurrent INCOME and OUTCOME on the same account must be
+    /// applied one after the other: none of them is lost or overdraws it,
+    /// and the stored balance stays equal to its ledger. `sqlx::test` runs it
+    /// in a fresh database on the server of `DATABASE_URL`, e.g. the one of
+    /// docker-compose, with the migrations applied.
+    #[sqlx::test]
+    async fn concurrent_postings_keep_the_balance_consistent(pool: PgPool) {
+        let user_id = Uuid::new_v4();
+        let account_id = Uuid::new_v4();
+
+        sqlx::query("INSERT INTO users (id, name, email, password) VALUES ($1, 'Test', 'test@example.com', '')")
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"INSERT INTO bank_accounts (id, name, balance, available_balance, currency, type, user_id)
+               VALUES ($1, 'Checking', 0, 0, 'USD', 'CHECKING', $2)"#,
+        )
+        .bind(account_id)
+        .bind(user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let opening = 10_000;
+        post(
+            &pool,
+            user_id,
+            account_id,
+            input(TransactionType::INCOME, opening),
+        )
+        .await
+        .unwrap();
+
+        let tasks: Vec<_> = (0..400)
+            .map(|index| {
+                let pool = pool.clone();
+                let (transaction_type, amount) = if index % 2 == 0 {
+                    (TransactionType::INCOME, INCOME)
+                } else {
+                    (TransactionType::OUTCOME, OUTCOME)
+                };
+
+                tokio::spawn(async move {
+                    let result =
+                        post(&pool, user_id, account_id, input(transaction_type, amount)).await;
+
+                    (index, result)
+                })
+            })
+            .collect();
+
+        let mut expected = opening;
+        let mut rejected = 0;
+
+        for task in tasks {
+            match task.await.unwrap() {
+                (index, Ok(posted)) => {
+                    assert!(
+                        posted.balance >= Money::default(),
+                        "overdrawn: {:?}",
+                        posted.balance
+                    );
+                    assert_eq!(posted.balance, posted.available_balance);
+
+                    expected += if index % 2 == 0 { INCOME } else { -OUTCOME };
+                }
+                (index, Err(err)) => {
+                    assert!(index % 2 == 1, "INCOME refused: {:?}", err);
+                    assert!(
+                        matches!(err, PostingError::Rejected(_)),
+                        "unexpected error: {:?}",
+                        err
+                    );
+
+                    rejected += 1;
+                }
+            }
+        }
+
+        assert!(rejected > 0, "the OUTCOMEs never exceeded the balance");
+
+        let (balance, ledger, outcomes): (i64, i64, i64) = sqlx::query_as(
+            r#"SELECT a.balance,
+                      (SELECT SUM(amount)::bigint FROM ledger_entries WHERE bank_account_id = a.id),
+                      (SELECT COUNT(*) FROM transactions WHERE origin_account_id = a.id AND transaction_type = 'OUTCOME')
+               FROM bank_accounts a
+               WHERE a.id = $1"#,
+        )
+        .bind(account_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(balance, expected);
+        assert_eq!(balance, ledger);
+        assert_eq!(outcomes, 200 - rejected);
+        assert!(balance >= 0);
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use sqlx::{postgres::PgPool, Acquire, PgConnection};
+use tokio::time::MissedTickBehavior;
+
+use crate::events::AccountEvents;
+use crate::models::schedule::ScheduledTransaction;
+use crate::posting::{self, PostedTransaction, PostingError, TransactionInput};
+use crate::tracing::{error, info};
+
+/// How often the scheduler looks for due occurrences.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Posts the due occurrences of the scheduled transactions, for as long as
+/// the server runs.
+pub async fn run(db_pool: Arc<PgPool>, events: AccountEvents) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = post_due_occurrences(&db_pool, &events).await {
+            error!("Error while posting scheduled transactions: {:?}", err);
+        }
+    }
+}
+
+/// Posts every occurrence due by now, each in its own DB transaction along
+/// with the record of the occurrence and the progress of its schedule, so a
+/// restart can neither skip nor repeat one. An occurrence that fails for
+/// another reason than a refusal is retried later, so one broken schedule
+/// doesn't hold up the others.
+async fn post_due_occurrences(
+    db_pool: &PgPool,
+    events: &AccountEvents,
+) -> Result<(), PostingError> {
+    loop {
+        let mut txn = db_pool.begin().await?;
+        let now = Utc::now().naive_utc();
+
+        let Some(mut schedule) = ScheduledTransaction::lock_next_due(&mut txn, now).await? else {
+            return Ok(());
+        };
+
+        // The schedule stays locked while the failed attempt is undone.
+        let mut attempt = (&mut *txn).begin().await?;
+
+        match post_occurrence(&mut attempt, &mut schedule).await {
+            Ok(posted) => {
+                attempt.commit().await?;
+                txn.commit().await?;
+
+                if let Some(posted) = posted {
+                    posted.publish(events);
+                }
+            }
+            Err(err) => {
+                attempt.rollback().await?;
+
+                error!(
+                    "Error while posting scheduled transaction {}: {:?}",
+                    schedule.id, err
+                );
+
+                schedule.postpone(&mut txn, now).await?;
+                txn.commit().await?;
+            }
+        }
+    }
+}
+
+/// Handles the current occurrence of the schedule and moves it on to the
+/// next one.
+async fn post_occurrence(
+    conn: &mut PgConnection,
+    schedule: &mut ScheduledTransaction,
+) -> Result<Option<PostedTransaction>, PostingError> {
+    let mut posted = None;
+
+    if schedule.claim_occurrence(&mut *conn).await? {
+        let input = TransactionInput {
+            transaction_type: schedule.transaction_type.clone(),
+            amount: schedule.amount,
+            category_id: schedule.category_id,
+            tags: schedule.tags.clone(),
+            pending: false,
+            currency: None,
+            occurred_at: None,
+        };
+
+        // A refused occurrence is recorded and skipped, so a schedule can't
+        // get stuck on an account without enough funds.
+        let mut savepoint = (&mut *conn).begin().await?;
+
+        let outcome = match posting::post_transaction(
+            &mut savepoint,
+            schedule.user_id,
+            schedule.account_id,
+            input,
+            schedule.description.clone(),
+        )
+        .await
+        {
+            Ok(transaction) => {
+                savepoint.commit().await?;
+                Ok(transaction)
+            }
+            Err(err) if err.is_rejection() => {
+                savepoint.rollback().await?;
+                Err(err.to_string())
+            }
+            Err(err) => return Err(err),
+        };
+
+        schedule
+            .finish_occurrence(
+                &mut *conn,
+                outcome
+                    .as_ref()
+                    .map(|posted| posted.transaction.id.as_str())
+                    .map_err(Clone::clone),
+            )
+            .await?;
+
+        match outcome {
+            Ok(transaction) => posted = Some(transaction),
+            Err(message) => info!(
+                "Skipped an occurrence of scheduled transaction {}: {}",
+                schedule.id, message
+            ),
+        }
+    }
+
+    schedule.advance();
+    schedule.save_progress(&mut *conn).await?;
+
+    Ok(posted)
+}
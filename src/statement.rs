@@ -0,0 +1,962 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::models::bank_account::{AccountType, BankAccount};
+use crate::models::currency::{Currency, CurrencyError};
+use crate::models::money::{Money, MoneyError};
+use crate::models::transaction::{Transaction, TransactionType};
+use crate::pagination::TIMESTAMP_FORMAT;
+
+const CSV_DATE_FORMAT: &str = "%Y-%m-%d";
+const OFX_DATE_FORMAT: &str = "%Y%m%d";
+const OFX_DATETIME_FORMAT: &str = "%Y%m%d%H%M%S";
+const MAX_EXTERNAL_ID_LENGTH: usize = 255;
+
+/// BANKID of the exported OFX statements. The accounts have no bank routing
+/// number, but importers expect the element to be there.
+const OFX_BANK_ID: &str = "000000000";
+
+#[derive(Error, Debug)]
+pub enum StatementError {
+    #[error("The statement is not valid UTF-8")]
+    Encoding,
+    #[error("Unsupported OFX charset {0}")]
+    Charset(String),
+    #[error("The CSV statement is missing the {0} column")]
+    MissingColumn(&'static str),
+    #[error("Invalid CSV statement: {0}")]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Money(#[from] MoneyError),
+    #[error(transparent)]
+    Currency(#[from] CurrencyError),
+    #[error("Invalid transaction timestamp: {0}")]
+    Timestamp(#[from] chrono::ParseError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// A transaction as read from a bank statement. The sign of the statement
+/// amount becomes the type: positive lines are income, negative ones outcome.
+#[derive(Debug)]
+pub struct StatementLine {
+    /// 1-based line of the statement the transaction starts at.
+    pub line: usize,
+    pub date: NaiveDate,
+    pub amount: Money,
+    pub transaction_type: TransactionType,
+    pub description: Option<String>,
+    /// OFX FITID or the CSV id column.
+    pub external_id: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct LineError {
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedStatement {
+    pub lines: Vec<StatementLine>,
+    pub errors: Vec<LineError>,
+}
+
+/// What identifies a statement line without a FITID.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Fingerprint {
+    date: NaiveDate,
+    transaction_type: String,
+    amount: Money,
+    description: Option<String>,
+}
+
+impl StatementLine {
+    pub fn to_transaction(&self, account_id: String) -> Transaction {
+        let mut transaction = Transaction::new(
+            self.amount,
+            self.transaction_type.clone(),
+            account_id,
+            self.description.clone(),
+        );
+        transaction.external_id = self.external_id.clone();
+        transaction.created_at = self.date.and_time(NaiveTime::MIN).and_utc().to_rfc3339();
+
+        transaction
+    }
+
+    fn fingerprint(&self) -> Fingerprint {
+        Fingerprint {
+            date: self.date,
+            transaction_type: self.transaction_type.to_string(),
+            amount: self.amount,
+            description: self.description.clone(),
+        }
+    }
+}
+
+/// Timestamps bounding the days the statement covers, the end being
+/// exclusive. Only transactions in this range can be duplicates.
+pub fn covered_range(lines: &[StatementLine]) -> Option<(String, String)> {
+    let first = lines.iter().map(|line| line.date).min()?;
+    let last = lines.iter().map(|line| line.date).max()?;
+
+    Some((
+        first.and_time(NaiveTime::MIN).to_string(),
+        last.succ_opt()?.and_time(NaiveTime::MIN).to_string(),
+    ))
+}
+
+/// Flags the statement lines already recorded on the account. Lines with a
+/// FITID are matched against `imported_ids`, the ids already imported into
+/// the account. The others are matched on their date, amount and
+/// description against `recorded`, the transactions of the days the
+/// statement covers. Each recorded transaction matches at most one line, so
+/// two identical purchases on the same day are both kept on the first
+/// import and both skipped on the next one.
+pub fn find_duplicates(
+    lines: &[StatementLine],
+    recorded: &[Transaction],
+    imported_ids: &[String],
+) -> Vec<bool> {
+    let mut external_ids: HashSet<&str> = imported_ids.iter().map(String::as_str).collect();
+    let mut fingerprints: HashMap<Fingerprint, usize> = HashMap::new();
+
+    for transaction in recorded {
+        let Some(date) = transaction
+            .created_at
+            .get(..10)
+            .and_then(|date| NaiveDate::parse_from_str(date, CSV_DATE_FORMAT).ok())
+        else {
+            continue;
+        };
+
+        let fingerprint = Fingerprint {
+            date,
+            transaction_type: transaction.transaction_type.to_string(),
+            amount: transaction.amount,
+            description: transaction.description.clone(),
+        };
+
+        *fingerprints.entry(fingerprint).or_default() += 1;
+    }
+
+    lines
+        .iter()
+        .map(|line| {
+            if let Some(external_id) = &line.external_id {
+                return !external_ids.insert(external_id);
+            }
+
+            match fingerprints.get_mut(&line.fingerprint()) {
+                Some(remaining) if *remaining > 0 => {
+                    *remaining -= 1;
+                    true
+                }
+                _ => false,
+            }
+        })
+        .collect()
+}
+
+/// Parses a signed statement amount such as `-12.50` into its type and
+/// absolute value, in minor units of the account currency.
+fn parse_amount(raw: &str, currency: &Currency) -> Result<(TransactionType, Money), String> {
+    let raw = raw.trim();
+
+    let (transaction_type, digits) = match raw.strip_prefix('-') {
+        Some(digits) => (TransactionType::OUTCOME, digits),
+        None => (
+            TransactionType::INCOME,
+            raw.strip_prefix('+').unwrap_or(raw),
+        ),
+    };
+
+    let amount = currency
+        .parse_amount(digits)
+        .map_err(|err: MoneyError| err.to_string())?;
+
+    if amount.is_zero() {
+        return Err("The amount must be different from zero".to_owned());
+    }
+
+    Ok((transaction_type, amount))
+}
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_owned)
+}
+
+fn parse_external_id(value: Option<&str>) -> Result<Option<String>, String> {
+    let external_id = non_empty(value);
+
+    if external_id
+        .as_ref()
+        .is_some_and(|external_id| external_id.len() > MAX_EXTERNAL_ID_LENGTH)
+    {
+        return Err(format!(
+            "The id must have at most {} characters",
+            MAX_EXTERNAL_ID_LENGTH
+        ));
+    }
+
+    Ok(external_id)
+}
+
+/// Parses a CSV statement with a header row. The `date` (YYYY-MM-DD) and
+/// `amount` columns are required; `description` (or `memo`), `id` and
+/// `currency` are optional. Column names are case-insensitive. Amounts are
+/// read in the account currency, so lines in another one are rejected.
+pub fn parse_csv(content: &[u8], currency: &Currency) -> Result<ParsedStatement, StatementError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(content);
+
+    let headers: Vec<String> = reader.headers()?.iter().map(str::to_lowercase).collect();
+
+    let column = |names: &[&str]| headers.iter().position(|header| names.contains(&&**header));
+
+    let date_column = column(&["date"]).ok_or(StatementError::MissingColumn("date"))?;
+    let amount_column = column(&["amount"]).ok_or(StatementError::MissingColumn("amount"))?;
+    let description_column = column(&["description", "memo"]);
+    let id_column = column(&["id", "fitid"]);
+    let currency_column = column(&["currency"]);
+
+    let mut statement = ParsedStatement::default();
+
+    for record in reader.records() {
+        let record = record?;
+        let line = record
+            .position()
+            .map(|position| position.line() as usize)
+            .unwrap_or_default();
+
+        let parsed = record
+            .get(date_column)
+            .and_then(|date| NaiveDate::parse_from_str(date, CSV_DATE_FORMAT).ok())
+            .ok_or_else(|| "Invalid date, expected YYYY-MM-DD".to_owned())
+            .and_then(|date| {
+                currency
+                    .ensure_matches(
+                        currency_column
+                            .and_then(|column| record.get(column))
+                            .unwrap_or_default(),
+                    )
+                    .map_err(|err| err.to_string())?;
+
+                let (transaction_type, amount) =
+                    parse_amount(record.get(amount_column).unwrap_or_default(), currency)?;
+                let external_id =
+                    parse_external_id(id_column.and_then(|column| record.get(column)))?;
+
+                Ok((date, transaction_type, amount, external_id))
+            });
+
+        match parsed {
+            Ok((date, transaction_type, amount, external_id)) => {
+                statement.lines.push(StatementLine {
+                    line,
+                    date,
+                    amount,
+                    transaction_type,
+                    description: non_empty(
+                        description_column.and_then(|column| record.get(column)),
+                    ),
+                    external_id,
+                })
+            }
+            Err(message) => statement.errors.push(LineError { line, message }),
+        }
+    }
+
+    Ok(statement)
+}
+
+/// Characters of the Windows-1252 bytes 0x80 to 0x9F, where it differs
+/// from Latin-1. The five unassigned bytes are kept as the C1 controls.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// Decodes an OFX statement. OFX 1.x files are SGML with a `KEY:VALUE`
+/// header whose `ENCODING` and `CHARSET` tell how the text is encoded; banks
+/// mostly send `USASCII` with `CHARSET:1252`. OFX 2.x files are XML, read as
+/// UTF-8.
+fn decode_ofx(content: &[u8]) -> Result<String, StatementError> {
+    let utf8 = |content: &[u8]| {
+        String::from_utf8(content.to_vec()).map_err(|_err| StatementError::Encoding)
+    };
+
+    let header_end = content
+        .windows(5)
+        .position(|window| window == b"<OFX>")
+        .unwrap_or(content.len());
+    let header = String::from_utf8_lossy(&content[..header_end]);
+
+    if header.contains("<?") {
+        return utf8(content);
+    }
+
+    let header_value = |name: &str| {
+        header.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == name).then(|| value.trim().to_uppercase())
+        })
+    };
+
+    if header_value("ENCODING").as_deref() == Some("UTF-8") {
+        return utf8(content);
+    }
+
+    match header_value("CHARSET").as_deref() {
+        None | Some("NONE") => utf8(content),
+        Some("1252") => Ok(content
+            .iter()
+            .map(|&byte| match byte {
+                0x80..=0x9F => WINDOWS_1252_HIGH[usize::from(byte - 0x80)],
+                _ => char::from(byte),
+            })
+            .collect()),
+        Some("ISO-8859-1" | "8859-1") => Ok(content.iter().map(|&byte| char::from(byte)).collect()),
+        Some(charset) => Err(StatementError::Charset(charset.to_owned())),
+    }
+}
+
+/// Reads the value of an OFX element. Both the SGML form of OFX 1.x, where
+/// elements are not closed, and the XML form of OFX 2.x are accepted. The
+/// entities `ofx_escape` writes are decoded.
+fn ofx_element(block: &str, name: &str) -> Option<String> {
+    let start = block.find(&format!("<{}>", name))? + name.len() + 2;
+    let value = &block[start..];
+    let end = value.find(['<', '\n', '\r']).unwrap_or(value.len());
+
+    Some(value[..end].trim())
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&amp;", "&")
+        })
+}
+
+/// Parses the `<STMTTRN>` blocks of an OFX or QFX statement. `DTPOSTED` and
+/// `TRNAMT` are required; lines without a `FITID` are told apart from the
+/// recorded transactions by their fingerprint, like CSV lines without an id.
+/// `MEMO`, or `NAME` when there is no memo, becomes the description. A
+/// statement whose `CURDEF` isn't the account currency is rejected, as are
+/// the lines with their own `<CURRENCY>`.
+pub fn parse_ofx(content: &[u8], currency: &Currency) -> Result<ParsedStatement, StatementError> {
+    let content = decode_ofx(content)?;
+
+    currency.ensure_matches(
+        ofx_element(&content, "CURDEF")
+            .as_deref()
+            .unwrap_or_default(),
+    )?;
+
+    let mut statement = ParsedStatement::default();
+
+    for (offset, _) in content.match_indices("<STMTTRN>") {
+        let line = content[..offset].matches('\n').count() + 1;
+
+        let block = &content[offset..];
+        let block = &block[..block.find("</STMTTRN>").unwrap_or(block.len())];
+
+        let parsed = ofx_element(block, "DTPOSTED")
+            .and_then(|date| NaiveDate::parse_from_str(date.get(..8)?, OFX_DATE_FORMAT).ok())
+            .ok_or_else(|| "Missing or invalid DTPOSTED".to_owned())
+            .and_then(|date| {
+                if let Some(start) = block.find("<CURRENCY>") {
+                    currency
+                        .ensure_matches(
+                            ofx_element(&block[start..], "CURSYM")
+                                .as_deref()
+                                .unwrap_or_default(),
+                        )
+                        .map_err(|err| err.to_string())?;
+                }
+
+                let (transaction_type, amount) = ofx_element(block, "TRNAMT")
+                    .ok_or_else(|| "Missing TRNAMT".to_owned())
+                    .and_then(|raw| parse_amount(&raw, currency))?;
+                let external_id = parse_external_id(ofx_element(block, "FITID").as_deref())?;
+
+                Ok((date, transaction_type, amount, external_id))
+            });
+
+        match parsed {
+            Ok((date, transaction_type, amount, external_id)) => {
+                statement.lines.push(StatementLine {
+                    line,
+                    date,
+                    amount,
+                    transaction_type,
+                    description: non_empty(
+                        ofx_element(block, "MEMO")
+                            .or_else(|| ofx_element(block, "NAME"))
+                            .as_deref(),
+                    ),
+                    external_id,
+                })
+            }
+            Err(message) => statement.errors.push(LineError { line, message }),
+        }
+    }
+
+    Ok(statement)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    Csv,
+    Ofx,
+    JsonLines,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum JsonRecord<'a> {
+    OpeningBalance {
+        account_id: &'a str,
+        currency: &'a str,
+        as_of: Option<String>,
+        balance_minor: i64,
+    },
+    Transaction {
+        id: &'a str,
+        created_at: String,
+        transaction_type: String,
+        amount_minor: i64,
+        balance_minor: i64,
+        description: Option<&'a str>,
+        external_id: Option<&'a str>,
+        transfer_id: Option<&'a str>,
+    },
+    ClosingBalance {
+        account_id: &'a str,
+        currency: &'a str,
+        as_of: String,
+        balance_minor: i64,
+    },
+}
+
+/// Renders an account statement piece by piece, so it can be streamed while
+/// the transactions are read page by page: `header` with the opening
+/// balance, `transactions` for every page, then `footer` with the closing
+/// balance. Period bounds and `created_at` are TIMESTAMP column text.
+/// Amounts are written with the decimal places of the account currency.
+pub struct StatementWriter {
+    format: ExportFormat,
+    account_id: String,
+    account_type: AccountType,
+    currency: Currency,
+    from: Option<NaiveDateTime>,
+    to: NaiveDateTime,
+    balance: Money,
+}
+
+fn parse_timestamp(raw: &str) -> Result<NaiveDateTime, StatementError> {
+    Ok(NaiveDateTime::parse_from_str(raw, TIMESTAMP_FORMAT)?)
+}
+
+fn rfc3339(timestamp: NaiveDateTime) -> String {
+    timestamp.and_utc().to_rfc3339()
+}
+
+/// OFX bank statements only know bank account types. Cash is kept like a
+/// checking account and investments like a money market one.
+fn ofx_account_type(account_type: &AccountType) -> &'static str {
+    match account_type {
+        AccountType::CHECKING | AccountType::CASH => "CHECKING",
+        AccountType::INVESTMENT => "MONEYMRKT",
+    }
+}
+
+fn ofx_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl StatementWriter {
+    pub fn new(
+        format: ExportFormat,
+        account: &BankAccount,
+        from: Option<&str>,
+        to: &str,
+        opening_balance: Money,
+    ) -> Result<StatementWriter, StatementError> {
+        Ok(StatementWriter {
+            format,
+            account_id: account.id.to_string(),
+            account_type: account.account_type.clone(),
+            currency: account.currency.clone(),
+            from: from.map(parse_timestamp).transpose()?,
+            to: parse_timestamp(to)?,
+            balance: opening_balance,
+        })
+    }
+
+    pub fn header(&self) -> Result<Vec<u8>, StatementError> {
+        let mut out = Vec::new();
+
+        match self.format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(&mut out);
+                writer.write_record([
+                    "date",
+                    "transaction_id",
+                    "type",
+                    "amount",
+                    "balance",
+                    "description",
+                    "external_id",
+                ])?;
+                writer.write_record([
+                    &self.from.map(rfc3339).unwrap_or_default(),
+                    "",
+                    "",
+                    "",
+                    &self.currency.format_amount(self.balance),
+                    "Opening balance",
+                    "",
+                ])?;
+                writer.flush().map_err(csv::Error::from)?;
+            }
+            ExportFormat::Ofx => {
+                let start = self
+                    .from
+                    .map(|from| from.format(OFX_DATETIME_FORMAT).to_string())
+                    .unwrap_or_else(|| "19700101000000".to_owned());
+
+                // OFX has no element for the opening balance; only the
+                // closing one is written, as LEDGERBAL.
+                out.extend_from_slice(
+                    format!(
+                        "OFXHEADER:100\n\
+                         DATA:OFXSGML\n\
+                         VERSION:102\n\
+                         SECURITY:NONE\n\
+                         ENCODING:UTF-8\n\
+                         CHARSET:NONE\n\
+                         COMPRESSION:NONE\n\
+                         OLDFILEUID:NONE\n\
+                         NEWFILEUID:NONE\n\
+                         \n\
+                         <OFX>\n\
+                         <BANKMSGSRSV1>\n\
+                         <STMTTRNRS>\n\
+                         <TRNUID>0\n\
+                         <STATUS><CODE>0<SEVERITY>INFO</STATUS>\n\
+                         <STMTRS>\n\
+                         <CURDEF>{}\n\
+                         <BANKACCTFROM>\n\
+                         <BANKID>{}\n\
+                         <ACCTID>{}\n\
+                         <ACCTTYPE>{}\n\
+                         </BANKACCTFROM>\n\
+                         <BANKTRANLIST>\n\
+                         <DTSTART>{}\n\
+                         <DTEND>{}\n",
+                        self.currency.code,
+                        OFX_BANK_ID,
+                        self.account_id,
+                        ofx_account_type(&self.account_type),
+                        start,
+                        self.to.format(OFX_DATETIME_FORMAT)
+                    )
+                    .as_bytes(),
+                );
+            }
+            ExportFormat::JsonLines => {
+                write_json(
+                    &mut out,
+                    &JsonRecord::OpeningBalance {
+                        account_id: &self.account_id,
+                        currency: &self.currency.code,
+                        as_of: self.from.map(rfc3339),
+                        balance_minor: self.balance.to_wire(),
+                    },
+                )?;
+            }
+        }
+
+        Ok(out)
+    }
+
+    pub fn transactions(
+        &mut self,
+        transactions: &[Transaction],
+    ) -> Result<Vec<u8>, StatementError> {
+        let mut out = Vec::new();
+        let mut csv_writer = csv::Writer::from_writer(Vec::new());
+
+        for transaction in transactions {
+            let signed_amount = match transaction.transaction_type {
+                TransactionType::INCOME => transaction.amount,
+                TransactionType::OUTCOME => -transaction.amount,
+            };
+            self.balance = self.balance.checked_add(signed_amount)?;
+
+            let created_at = parse_timestamp(&transaction.created_at)?;
+
+            match self.format {
+                ExportFormat::Csv => {
+                    csv_writer.write_record([
+                        &rfc3339(created_at),
+                        &transaction.id,
+                        &transaction.transaction_type.to_string(),
+                        &self.currency.format_amount(signed_amount),
+                        &self.currency.format_amount(self.balance),
+                        transaction.description.as_deref().unwrap_or_default(),
+                        transaction.external_id.as_deref().unwrap_or_default(),
+                    ])?;
+                }
+                ExportFormat::Ofx => {
+                    let transaction_type = match transaction.transaction_type {
+                        TransactionType::INCOME => "CREDIT",
+                        TransactionType::OUTCOME => "DEBIT",
+                    };
+                    let memo = transaction
+                        .description
+                        .as_deref()
+                        .map(|description| format!("<MEMO>{}\n", ofx_escape(description)))
+                        .unwrap_or_default();
+
+                    out.extend_from_slice(
+                        format!(
+                            "<STMTTRN>\n\
+                             <TRNTYPE>{}\n\
+                             <DTPOSTED>{}\n\
+                             <TRNAMT>{}\n\
+                             <FITID>{}\n\
+                             {}\
+                             </STMTTRN>\n",
+                            transaction_type,
+                            created_at.format(OFX_DATETIME_FORMAT),
+                            self.currency.format_amount(signed_amount),
+                            ofx_escape(
+                                transaction
+                                    .external_id
+                                    .as_deref()
+                                    .unwrap_or(&transaction.id)
+                            ),
+                            memo
+                        )
+                        .as_bytes(),
+                    );
+                }
+                ExportFormat::JsonLines => {
+                    write_json(
+                        &mut out,
+                        &JsonRecord::Transaction {
+                            id: &transaction.id,
+                            created_at: rfc3339(created_at),
+                            transaction_type: transaction.transaction_type.to_string(),
+                            amount_minor: transaction.amount.to_wire(),
+                            balance_minor: self.balance.to_wire(),
+                            description: transaction.description.as_deref(),
+                            external_id: transaction.external_id.as_deref(),
+                            transfer_id: transaction.transfer_id.as_deref(),
+                        },
+                    )?;
+                }
+            }
+        }
+
+        if let ExportFormat::Csv = self.format {
+            out = csv_writer
+                .into_inner()
+                .map_err(|err| csv::Error::from(err.into_error()))?;
+        }
+
+        Ok(out)
+    }
+
+    pub fn footer(&self) -> Result<Vec<u8>, StatementError> {
+        let mut out = Vec::new();
+
+        match self.format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(&mut out);
+                writer.write_record([
+                    &rfc3339(self.to),
+                    "",
+                    "",
+                    "",
+                    &self.currency.format_amount(self.balance),
+                    "Closing balance",
+                    "",
+                ])?;
+                writer.flush().map_err(csv::Error::from)?;
+            }
+            ExportFormat::Ofx => {
+                out.extend_from_slice(
+                    format!(
+                        "</BANKTRANLIST>\n\
+                         <LEDGERBAL><BALAMT>{}<DTASOF>{}</LEDGERBAL>\n\
+                         </STMTRS>\n\
+                         </STMTTRNRS>\n\
+                         </BANKMSGSRSV1>\n\
+                         </OFX>\n",
+                        self.currency.format_amount(self.balance),
+                        self.to.format(OFX_DATETIME_FORMAT)
+                    )
+                    .as_bytes(),
+                );
+            }
+            ExportFormat::JsonLines => {
+                write_json(
+                    &mut out,
+                    &JsonRecord::ClosingBalance {
+                        account_id: &self.account_id,
+                        currency: &self.currency.code,
+                        as_of: rfc3339(self.to),
+                        balance_minor: self.balance.to_wire(),
+                    },
+                )?;
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+fn write_json(out: &mut Vec<u8>, record: &JsonRecord) -> Result<(), StatementError> {
+    serde_json::to_writer(&mut *out, record)?;
+    out.push(b'\n');
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usd() -> Currency {
+        Currency::new("USD".to_owned(), 2)
+    }
+
+    fn recorded(date: &str, amount: i64, description: &str) -> Transaction {
+        let mut transaction = Transaction::new(
+            Money::from_wire(amount).unwrap(),
+            TransactionType::OUTCOME,
+            "account".to_owned(),
+            Some(description.to_owned()),
+        );
+        transaction.created_at = format!("{} 09:30:00", date);
+
+        transaction
+    }
+
+    const SGML_OFX: &str = "OFXHEADER:100\n\
+        DATA:OFXSGML\n\
+        ENCODING:USASCII\n\
+        CHARSET:1252\n\
+        \n\
+        <OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS>\n\
+        <CURDEF>USD\n\
+        <BANKTRANLIST>\n\
+        <STMTTRN>\n\
+        <TRNTYPE>DEBIT\n\
+        <DTPOSTED>20240131120000[-5:EST]\n\
+        <TRNAMT>-12.50\n\
+        <FITID>A1\n\
+        <NAME>Grocer\n\
+        <MEMO>Caf\u{E9}\u{92}s &amp; more\n\
+        </STMTTRN>\n\
+        <STMTTRN>\n\
+        <TRNTYPE>CREDIT\n\
+        <DTPOSTED>20240201\n\
+        <TRNAMT>+1500\n\
+        <NAME>Salary\n\
+        </STMTTRN>\n\
+        </BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>\n";
+
+    #[test]
+    fn parses_sgml_ofx_in_windows_1252() {
+        // Every character is below U+0100, so these are the file bytes.
+        let content: Vec<u8> = SGML_OFX.chars().map(|c| c as u8).collect();
+
+        let statement = parse_ofx(&content, &usd()).unwrap();
+
+        assert!(statement.errors.is_empty(), "{:?}", statement.errors);
+        assert_eq!(statement.lines.len(), 2);
+
+        let purchase = &statement.lines[0];
+        assert_eq!(purchase.date, NaiveDate::from_ymd_opt(2024, 1, 31).unwrap());
+        assert_eq!(purchase.transaction_type, TransactionType::OUTCOME);
+        assert_eq!(purchase.amount.to_wire(), 1250);
+        assert_eq!(purchase.description.as_deref(), Some("Café’s & more"));
+        assert_eq!(purchase.external_id.as_deref(), Some("A1"));
+
+        let salary = &statement.lines[1];
+        assert_eq!(salary.transaction_type, TransactionType::INCOME);
+        assert_eq!(salary.amount.to_wire(), 150000);
+        assert_eq!(salary.description.as_deref(), Some("Salary"));
+        assert_eq!(salary.external_id, None);
+    }
+
+    #[test]
+    fn parses_xml_ofx_as_utf8() {
+        let content = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <?OFX OFXHEADER=\"200\" VERSION=\"220\"?>\n\
+            <OFX><CURDEF>USD</CURDEF>\n\
+            <STMTTRN><TRNTYPE>DEBIT</TRNTYPE><DTPOSTED>20240131</DTPOSTED>\
+            <TRNAMT>-3.20</TRNAMT><FITID>X&lt;1&gt;</FITID><MEMO>Café</MEMO></STMTTRN>\n\
+            </OFX>\n";
+
+        let statement = parse_ofx(content.as_bytes(), &usd()).unwrap();
+
+        assert!(statement.errors.is_empty(), "{:?}", statement.errors);
+        assert_eq!(statement.lines[0].amount.to_wire(), 320);
+        assert_eq!(statement.lines[0].description.as_deref(), Some("Café"));
+        assert_eq!(statement.lines[0].external_id.as_deref(), Some("X<1>"));
+    }
+
+    #[test]
+    fn rejects_unknown_ofx_charsets_and_invalid_utf8() {
+        let content = b"OFXHEADER:100\nCHARSET:437\n\n<OFX>\n";
+        assert!(matches!(
+            parse_ofx(content, &usd()),
+            Err(StatementError::Charset(charset)) if charset == "437"
+        ));
+
+        let content = b"OFXHEADER:100\nENCODING:UTF-8\n\n<OFX><MEMO>Caf\xE9\n";
+        assert!(matches!(
+            parse_ofx(content, &usd()),
+            Err(StatementError::Encoding)
+        ));
+    }
+
+    #[test]
+    fn reports_invalid_ofx_lines() {
+        let content = "<OFX><CURDEF>USD\n\
+            <STMTTRN><DTPOSTED>2024<TRNAMT>-1.00</STMTTRN>\n\
+            <STMTTRN><DTPOSTED>20240101<TRNAMT>0.00</STMTTRN>\n\
+            <STMTTRN><DTPOSTED>20240101</STMTTRN>\n";
+
+        let statement = parse_ofx(content.as_bytes(), &usd()).unwrap();
+
+        assert!(statement.lines.is_empty());
+        let errors: Vec<_> = statement
+            .errors
+            .iter()
+            .map(|error| (error.line, error.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (2, "Missing or invalid DTPOSTED"),
+                (3, "The amount must be different from zero"),
+                (4, "Missing TRNAMT"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_amounts_in_the_account_currency() {
+        let yen = Currency::new("JPY".to_owned(), 0);
+        let dinar = Currency::new("BHD".to_owned(), 3);
+
+        let (transaction_type, amount) = parse_amount("-1500", &yen).unwrap();
+        assert_eq!(transaction_type, TransactionType::OUTCOME);
+        assert_eq!(amount.to_wire(), 1500);
+        assert!(parse_amount("1.5", &yen).is_err());
+
+        let (transaction_type, amount) = parse_amount(" +1.234 ", &dinar).unwrap();
+        assert_eq!(transaction_type, TransactionType::INCOME);
+        assert_eq!(amount.to_wire(), 1234);
+        assert!(parse_amount("1.2345", &dinar).is_err());
+
+        assert!(parse_amount("-0.00", &usd()).is_err());
+        assert!(parse_amount("--1", &usd()).is_err());
+        assert!(parse_amount("", &usd()).is_err());
+    }
+
+    #[test]
+    fn parses_csv_statements() {
+        let content = "Date,Amount,Memo,ID,Currency\n\
+            2024-01-31,-12.50,Coffee,,\n\
+            2024-02-01,100,,T2,usd\n\
+            2024-02-01,1,Refund,,EUR\n\
+            31/01/2024,1,,,\n";
+
+        let statement = parse_csv(content.as_bytes(), &usd()).unwrap();
+
+        assert_eq!(statement.lines.len(), 2);
+        assert_eq!(statement.lines[0].line, 2);
+        assert_eq!(statement.lines[0].transaction_type, TransactionType::OUTCOME);
+        assert_eq!(statement.lines[0].amount.to_wire(), 1250);
+        assert_eq!(statement.lines[0].description.as_deref(), Some("Coffee"));
+        assert_eq!(statement.lines[0].external_id, None);
+        assert_eq!(statement.lines[1].external_id.as_deref(), Some("T2"));
+        assert_eq!(statement.lines[1].description, None);
+
+        let lines: Vec<_> = statement.errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, [4, 5]);
+    }
+
+    #[test]
+    fn rejects_csv_statements_without_a_date_column() {
+        let content = "when,amount\n2024-01-31,-12.50\n";
+
+        assert!(matches!(
+            parse_csv(content.as_bytes(), &usd()),
+            Err(StatementError::MissingColumn("date"))
+        ));
+    }
+
+    #[test]
+    fn covers_the_days_of_the_statement() {
+        let content = "date,amount\n2024-02-29,1\n2024-02-10,1\n";
+        let statement = parse_csv(content.as_bytes(), &usd()).unwrap();
+
+        assert_eq!(
+            covered_range(&statement.lines),
+            Some((
+                "2024-02-10 00:00:00".to_owned(),
+                "2024-03-01 00:00:00".to_owned()
+            ))
+        );
+        assert_eq!(covered_range(&[]), None);
+    }
+
+    #[test]
+    fn finds_duplicates_by_fitid_and_fingerprint() {
+        let content = "date,amount,description,id\n\
+            2024-01-31,-12.50,Coffee,\n\
+            2024-01-31,-12.50,Coffee,\n\
+            2024-01-31,-12.50,Coffee,\n\
+            2024-01-31,-12.50,Tea,\n\
+            2024-01-31,-5.00,Lunch,T1\n\
+            2024-01-31,-5.00,Lunch,T2\n\
+            2024-01-31,-5.00,Lunch,T2\n";
+        let statement = parse_csv(content.as_bytes(), &usd()).unwrap();
+
+        let recorded = [
+            recorded("2024-01-31", 1250, "Coffee"),
+            recorded("2024-01-31", 1250, "Coffee"),
+            recorded("2024-01-30", 1250, "Tea"),
+        ];
+
+        assert_eq!(
+            find_duplicates(&statement.lines, &recorded, &["T1".to_owned()]),
+            [true, true, false, false, true, false, true]
+        );
+    }
+}